@@ -0,0 +1,464 @@
+// Amount fingerprinting over ledger.db
+// Finds exact non-round amounts reused by several senders, accounts that mostly move round numbers,
+// and transfers sitting just under reporting thresholds; shared rare amounts link senders into clusters
+
+use crate::{
+    clustering::DisjointSet,
+    entities::label_map,
+    ledger_db::{DbTransaction, LedgerDatabase},
+    transfer_graph::TimeWindow,
+    Type,
+};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::{hash_map::Entry, BTreeSet, HashMap};
+
+const E8S_PER_ICP: u64 = 100_000_000;
+
+#[derive(Debug, Clone)]
+pub struct AmountConfig {
+    /// Transfers below this are ignored
+    pub min_amount: u64,
+    /// An exact amount used by more senders than this is common, not a fingerprint
+    pub max_accounts_per_amount: usize,
+    /// Accounts need this many transfers before their round-number share is judged
+    pub min_transfers: usize,
+    /// Share of whole-ICP transfers that marks an account as a round-number mover
+    pub min_round_share: f64,
+    /// Reporting thresholds in e8s
+    pub thresholds: Vec<u64>,
+    /// How far below a threshold, as a share of it, a transfer counts as structured
+    pub threshold_margin: f64,
+    /// Structured transfers an account needs before it is reported
+    pub min_structured: usize,
+    pub window: TimeWindow,
+}
+
+impl Default for AmountConfig {
+    fn default() -> Self {
+        Self {
+            min_amount: E8S_PER_ICP,
+            max_accounts_per_amount: 10,
+            min_transfers: 10,
+            min_round_share: 0.8,
+            thresholds: vec![1_000 * E8S_PER_ICP, 10_000 * E8S_PER_ICP, 100_000 * E8S_PER_ICP],
+            threshold_margin: 0.05,
+            min_structured: 3,
+            window: TimeWindow::default(),
+        }
+    }
+}
+
+impl AmountConfig {
+    /// Parse a comma-separated list of thresholds in ICP
+    pub fn parse_thresholds(spec: &str) -> Result<Vec<u64>> {
+        spec.split(',')
+            .map(|s| {
+                s.trim()
+                    .parse::<f64>()
+                    .map(|icp| (icp * E8S_PER_ICP as f64) as u64)
+                    .map_err(|_| anyhow!("invalid threshold '{}'", s))
+            })
+            .collect()
+    }
+}
+
+/// Whole ICP amounts
+pub fn is_round(amount: u64) -> bool {
+    amount.is_multiple_of(E8S_PER_ICP)
+}
+
+/// Amounts with more precision than cents are unlikely to be picked twice by chance
+pub fn is_exact(amount: u64) -> bool {
+    !amount.is_multiple_of(1_000_000)
+}
+
+/// Distinct senders of one exact amount; most amounts have a single sender, which is kept inline
+#[derive(Debug)]
+struct AmountSenders {
+    first: usize,
+    /// Cleared once too many senders use the amount
+    others: Vec<usize>,
+    common: bool,
+}
+
+impl AmountSenders {
+    fn is_shared(&self) -> bool {
+        !self.common && !self.others.is_empty()
+    }
+
+    fn all(&self) -> BTreeSet<usize> {
+        std::iter::once(self.first).chain(self.others.iter().copied()).collect()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct SenderStats {
+    transfers: usize,
+    round: usize,
+    round_volume: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SharedAmount {
+    pub amount: u64,
+    pub accounts: Vec<String>,
+    pub blocks: Vec<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoundAccount {
+    pub account: String,
+    pub label: Option<String>,
+    pub transfers: usize,
+    pub round_share: f64,
+    pub round_volume: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StructuringAccount {
+    pub account: String,
+    pub label: Option<String>,
+    pub threshold: u64,
+    pub transfers: usize,
+    pub total: u64,
+    pub blocks: Vec<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AmountCluster {
+    pub accounts: Vec<String>,
+    pub labels: Vec<(String, String)>,
+    pub evidence: Vec<SharedAmount>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AmountReport {
+    pub transfers_scanned: usize,
+    pub shared_amounts: Vec<SharedAmount>,
+    pub round_accounts: Vec<RoundAccount>,
+    pub structuring: Vec<StructuringAccount>,
+    pub clusters: Vec<AmountCluster>,
+}
+
+/// Collects amount statistics while streaming the ledger twice
+///
+/// The first pass (`observe`) only counts senders per exact amount; the second (`observe_evidence`)
+/// collects blocks for the amounts shared by a few senders, so the blocks of the many amounts used
+/// once are never held in memory.
+pub struct AmountCollector {
+    config: AmountConfig,
+    ids: HashMap<String, usize>,
+    accounts: Vec<String>,
+    amounts: HashMap<u64, AmountSenders>,
+    // Blocks of every shared amount, filled in the second pass
+    evidence: HashMap<u64, Vec<u64>>,
+    senders: Vec<SenderStats>,
+    // (sender id, threshold index) -> (total, blocks)
+    structured: HashMap<(usize, usize), (u64, Vec<u64>)>,
+    transfers_scanned: usize,
+}
+
+impl AmountCollector {
+    pub fn new(config: AmountConfig) -> Self {
+        Self {
+            config,
+            ids: HashMap::new(),
+            accounts: Vec::new(),
+            amounts: HashMap::new(),
+            evidence: HashMap::new(),
+            senders: Vec::new(),
+            structured: HashMap::new(),
+            transfers_scanned: 0,
+        }
+    }
+
+    fn account_id(&mut self, account: &str) -> usize {
+        if let Some(id) = self.ids.get(account) {
+            return *id;
+        }
+        let id = self.accounts.len();
+        self.ids.insert(account.to_string(), id);
+        self.accounts.push(account.to_string());
+        self.senders.push(SenderStats::default());
+        id
+    }
+
+    /// Sender and amount of a transfer both passes look at
+    fn eligible<'a>(&self, tx: &'a DbTransaction) -> Option<(&'a String, u64)> {
+        let (Some(from), Some(amount)) = (&tx.from_account, tx.amount) else { return None };
+        if tx.operation_type != "Transfer"
+            || amount < self.config.min_amount
+            || !tx.timestamp.is_some_and(|ts| self.config.window.contains(ts))
+        {
+            return None;
+        }
+        Some((from, amount))
+    }
+
+    /// First pass: sender statistics, structuring, and distinct senders per exact amount
+    pub fn observe(&mut self, tx: &DbTransaction) {
+        let Some((from, amount)) = self.eligible(tx) else { return };
+        self.transfers_scanned += 1;
+        let sender = self.account_id(from);
+
+        let stats = &mut self.senders[sender];
+        stats.transfers += 1;
+        if is_round(amount) {
+            stats.round += 1;
+            stats.round_volume += amount;
+        }
+
+        if is_exact(amount) {
+            match self.amounts.entry(amount) {
+                Entry::Vacant(entry) => {
+                    entry.insert(AmountSenders { first: sender, others: Vec::new(), common: false });
+                }
+                Entry::Occupied(mut entry) => {
+                    let senders = entry.get_mut();
+                    if !senders.common && senders.first != sender && !senders.others.contains(&sender) {
+                        if senders.others.len() + 2 > self.config.max_accounts_per_amount {
+                            senders.others = Vec::new();
+                            senders.common = true;
+                        } else {
+                            senders.others.push(sender);
+                        }
+                    }
+                }
+            }
+        }
+
+        for (i, threshold) in self.config.thresholds.iter().enumerate() {
+            let floor = (*threshold as f64 * (1.0 - self.config.threshold_margin)) as u64;
+            if amount >= floor && amount < *threshold {
+                let entry = self.structured.entry((sender, i)).or_default();
+                entry.0 += amount;
+                entry.1.push(tx.id);
+            }
+        }
+    }
+
+    /// End the first pass, keeping only amounts shared by 2..=max senders
+    pub fn start_evidence_pass(&mut self) {
+        self.amounts.retain(|_, senders| senders.is_shared());
+        self.amounts.shrink_to_fit();
+        self.evidence = self.amounts.keys().map(|amount| (*amount, Vec::new())).collect();
+    }
+
+    /// Second pass: blocks of the transfers using a shared amount
+    pub fn observe_evidence(&mut self, tx: &DbTransaction) {
+        let Some((_, amount)) = self.eligible(tx) else { return };
+        if let Some(blocks) = self.evidence.get_mut(&amount) {
+            blocks.push(tx.id);
+        }
+    }
+
+    pub fn finish(mut self) -> AmountReport {
+        let labels = label_map();
+        let label = |account: &str| labels.get(account).map(|(name, ty)| format!("{} ({})", name, ty));
+        // Exchanges and other services pay out the same amounts to many customers
+        let is_service = |id: usize| labels.get(&self.accounts[id]).is_some_and(|(_, ty)| *ty == Type::Cex);
+
+        let mut shared: Vec<(BTreeSet<usize>, SharedAmount)> = self
+            .amounts
+            .iter()
+            .filter(|(_, usage)| usage.is_shared())
+            .map(|(amount, usage)| (*amount, usage.all()))
+            .filter(|(_, senders)| !senders.iter().any(|id| is_service(*id)))
+            .map(|(amount, senders)| {
+                let shared = SharedAmount {
+                    amount,
+                    accounts: senders.iter().map(|id| self.accounts[*id].clone()).collect(),
+                    blocks: self.evidence.remove(&amount).unwrap_or_default(),
+                };
+                (senders, shared)
+            })
+            .collect();
+        shared.sort_by_key(|(senders, s)| (std::cmp::Reverse(senders.len()), s.amount));
+
+        let mut round_accounts: Vec<RoundAccount> = self
+            .senders
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| {
+                s.transfers >= self.config.min_transfers
+                    && s.round as f64 >= s.transfers as f64 * self.config.min_round_share
+            })
+            .map(|(id, s)| RoundAccount {
+                account: self.accounts[id].clone(),
+                label: label(&self.accounts[id]),
+                transfers: s.transfers,
+                round_share: s.round as f64 / s.transfers as f64,
+                round_volume: s.round_volume,
+            })
+            .collect();
+        round_accounts.sort_by_key(|r| std::cmp::Reverse(r.round_volume));
+
+        let mut structuring: Vec<StructuringAccount> = self
+            .structured
+            .into_iter()
+            .filter(|(_, (_, blocks))| blocks.len() >= self.config.min_structured)
+            .map(|((id, threshold), (total, mut blocks))| {
+                blocks.sort_unstable();
+                StructuringAccount {
+                    account: self.accounts[id].clone(),
+                    label: label(&self.accounts[id]),
+                    threshold: self.config.thresholds[threshold],
+                    transfers: blocks.len(),
+                    total,
+                    blocks,
+                }
+            })
+            .collect();
+        structuring.sort_by_key(|s| (std::cmp::Reverse(s.transfers), s.account.clone()));
+
+        // Senders sharing a rare amount are linked; the shared amounts are the evidence
+        let mut set = DisjointSet::new(self.accounts.len());
+        for (senders, _) in &shared {
+            let first = *senders.iter().next().unwrap();
+            for other in senders {
+                set.union(first, *other);
+            }
+        }
+        let mut grouped: HashMap<usize, (BTreeSet<usize>, Vec<SharedAmount>)> = HashMap::new();
+        for (senders, amount) in &shared {
+            let group = grouped.entry(set.find(*senders.iter().next().unwrap())).or_default();
+            group.0.extend(senders.iter().copied());
+            group.1.push(amount.clone());
+        }
+        let mut clusters: Vec<AmountCluster> = grouped
+            .into_values()
+            .map(|(members, evidence)| {
+                let accounts: Vec<String> = members.iter().map(|id| self.accounts[*id].clone()).collect();
+                AmountCluster {
+                    labels: accounts.iter().filter_map(|a| label(a).map(|l| (a.clone(), l))).collect(),
+                    accounts,
+                    evidence,
+                }
+            })
+            .collect();
+        clusters.sort_by_key(|c| (std::cmp::Reverse(c.evidence.len()), std::cmp::Reverse(c.accounts.len())));
+
+        AmountReport {
+            transfers_scanned: self.transfers_scanned,
+            shared_amounts: shared.into_iter().map(|(_, s)| s).collect(),
+            round_accounts,
+            structuring,
+            clusters,
+        }
+    }
+}
+
+/// Run amount fingerprinting over ledger.db
+pub async fn run_amount_patterns(db_path: &str, config: AmountConfig) -> Result<()> {
+    println!("===== AMOUNT FINGERPRINTING =====");
+    println!("Database: {}", db_path);
+    println!(
+        "Thresholds: {} ICP (margin {:.0}%)",
+        config.thresholds.iter().map(|t| (t / E8S_PER_ICP).to_string()).collect::<Vec<_>>().join(", "),
+        config.threshold_margin * 100.0
+    );
+
+    let db = LedgerDatabase::new(db_path)?;
+    let mut collector = AmountCollector::new(config);
+    db.for_each_transaction(None, |tx| {
+        collector.observe(&tx);
+        Ok(())
+    })?;
+    collector.start_evidence_pass();
+    db.for_each_transaction(None, |tx| {
+        collector.observe_evidence(&tx);
+        Ok(())
+    })?;
+    let report = collector.finish();
+
+    println!("Transfers scanned: {}", report.transfers_scanned);
+    println!("\nExact amounts shared across senders: {}", report.shared_amounts.len());
+    for shared in report.shared_amounts.iter().take(10) {
+        println!(
+            "  {:.8} ICP used by {} accounts in {} transfers",
+            shared.amount as f64 / E8S_PER_ICP as f64,
+            shared.accounts.len(),
+            shared.blocks.len()
+        );
+    }
+    println!("\nRound-number accounts: {}", report.round_accounts.len());
+    println!("Accounts structuring below thresholds: {}", report.structuring.len());
+    for s in report.structuring.iter().take(10) {
+        println!(
+            "  {} {} transfers just under {} ICP{}",
+            s.account,
+            s.transfers,
+            s.threshold / E8S_PER_ICP,
+            s.label.as_ref().map(|l| format!(" - {}", l)).unwrap_or_default()
+        );
+    }
+    println!("\nClusters linked by shared amounts: {}", report.clusters.len());
+    for cluster in report.clusters.iter().take(10) {
+        println!("  {} accounts, {} shared amounts", cluster.accounts.len(), cluster.evidence.len());
+    }
+
+    let file_name = "./amount_patterns.json";
+    std::fs::write(file_name, serde_json::to_string_pretty(&report)?)?;
+
+    println!("\nAmount patterns saved to: {}", file_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger_db::test_transaction;
+
+    fn tx(id: u64, from: &str, amount: u64) -> DbTransaction {
+        DbTransaction { fee: Some(10_000), ..test_transaction(id, "Transfer", Some(from), Some("sink"), amount) }
+    }
+
+    #[test]
+    fn test_amount_patterns() {
+        let config = AmountConfig { min_transfers: 3, ..AmountConfig::default() };
+        let mut collector = AmountCollector::new(config);
+        let transactions = [
+            // a and b both send 12.34567891 ICP, b and c both send 7.77777777 ICP
+            tx(1, "a", 1_234_567_891),
+            tx(2, "b", 1_234_567_891),
+            tx(3, "b", 777_777_777),
+            tx(4, "c", 777_777_777),
+            // round numbers only
+            tx(5, "round", 5 * E8S_PER_ICP),
+            tx(6, "round", 20 * E8S_PER_ICP),
+            tx(7, "round", 300 * E8S_PER_ICP),
+            // just under 10k ICP, three times
+            tx(8, "smurf", 9_900 * E8S_PER_ICP),
+            tx(9, "smurf", 9_800 * E8S_PER_ICP + 12_345),
+            tx(10, "smurf", 9_990 * E8S_PER_ICP),
+            // cent precision is not an exact fingerprint
+            tx(11, "d", 150_000_000),
+            tx(12, "e", 150_000_000),
+        ];
+        for t in &transactions {
+            collector.observe(t);
+        }
+        collector.start_evidence_pass();
+        for t in &transactions {
+            collector.observe_evidence(t);
+        }
+
+        let report = collector.finish();
+        assert_eq!(report.shared_amounts.len(), 2);
+        assert_eq!(report.shared_amounts[0].blocks, vec![3, 4]);
+        assert_eq!(report.shared_amounts[1].blocks, vec![1, 2]);
+        assert_eq!(report.clusters.len(), 1);
+        assert_eq!(report.clusters[0].accounts, vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+        assert_eq!(report.clusters[0].evidence.len(), 2);
+
+        assert_eq!(report.round_accounts.len(), 1);
+        assert_eq!(report.round_accounts[0].account, "round");
+
+        assert_eq!(report.structuring.len(), 1);
+        assert_eq!(report.structuring[0].account, "smurf");
+        assert_eq!(report.structuring[0].threshold, 10_000 * E8S_PER_ICP);
+        assert_eq!(report.structuring[0].blocks, vec![8, 9, 10]);
+    }
+}
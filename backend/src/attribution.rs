@@ -0,0 +1,433 @@
+// Backward source attribution
+// Splits an account's balance at a point in time into where the funds originally came from:
+// mints (by category), labelled entities, or unlabelled accounts at the hop limit
+
+use crate::{
+    entities::label_map,
+    ledger_db::{DbTransaction, LedgerDatabase},
+    replay::PointInTime,
+    supply::{classify_mint, MintCategory},
+    taint::Policy,
+    Type,
+};
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// Where a piece of an account's balance entered it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Origin {
+    Mint(MintCategory),
+    Transfer {
+        from: String,
+        block: u64,
+    },
+    /// Outflows larger than the known inflows (missing history)
+    Unknown,
+}
+
+/// Final attribution target
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Source {
+    Mint { category: MintCategory },
+    Entity { name: String, category: String },
+    Unlabelled { account: String },
+    Unexplained,
+}
+
+/// Composition of every outflow and of the final balance of one account
+#[derive(Debug, Default)]
+struct Simulation {
+    outflows: HashMap<u64, Vec<(Origin, u64)>>,
+    holdings: Vec<(Origin, u64)>,
+    balance: u64,
+}
+
+/// Funds held by an account, split by origin, mixed according to a policy
+struct Pool {
+    policy: Policy,
+    lots: VecDeque<(Origin, u64)>,
+}
+
+impl Pool {
+    fn add(&mut self, origin: Origin, amount: u64) {
+        if amount == 0 {
+            return;
+        }
+        match self.policy {
+            Policy::Fifo | Policy::Lifo => self.lots.push_back((origin, amount)),
+            Policy::ProRata | Policy::Poison => match self.lots.iter_mut().find(|(o, _)| *o == origin) {
+                Some((_, held)) => *held += amount,
+                None => self.lots.push_back((origin, amount)),
+            },
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.lots.iter().map(|(_, amount)| amount).sum()
+    }
+
+    /// Remove `amount`, returning the origins it is made of
+    fn take(&mut self, amount: u64) -> Vec<(Origin, u64)> {
+        let total = self.total();
+        let mut taken = Vec::new();
+
+        match self.policy {
+            Policy::Fifo | Policy::Lifo => {
+                let mut remaining = amount;
+                while remaining > 0 {
+                    let lot = if self.policy == Policy::Lifo { self.lots.back_mut() } else { self.lots.front_mut() };
+                    let Some((origin, held)) = lot else { break };
+
+                    let part = (*held).min(remaining);
+                    taken.push((origin.clone(), part));
+                    *held -= part;
+                    remaining -= part;
+
+                    if *held == 0 {
+                        if self.policy == Policy::Lifo {
+                            self.lots.pop_back();
+                        } else {
+                            self.lots.pop_front();
+                        }
+                    }
+                }
+                if remaining > 0 {
+                    taken.push((Origin::Unknown, remaining));
+                }
+            }
+            Policy::ProRata => {
+                let covered = amount.min(total);
+                for (origin, held) in self.lots.iter_mut() {
+                    let part = (*held as u128 * covered as u128 / total.max(1) as u128) as u64;
+                    *held -= part;
+                    taken.push((origin.clone(), part));
+                }
+                self.lots.retain(|(_, held)| *held > 0);
+                if amount > covered {
+                    taken.push((Origin::Unknown, amount - covered));
+                }
+            }
+            Policy::Poison => {
+                // Every origin is credited with the whole outflow
+                taken = self.lots.iter().map(|(origin, _)| (origin.clone(), amount)).collect();
+                if taken.is_empty() {
+                    taken.push((Origin::Unknown, amount));
+                }
+                let mut remaining = amount.min(total);
+                for (_, held) in self.lots.iter_mut() {
+                    let part = (*held).min(remaining);
+                    *held -= part;
+                    remaining -= part;
+                }
+            }
+        }
+
+        taken
+    }
+}
+
+/// Replay one account's own transactions up to and including `last_block`
+fn simulate(
+    account: &str,
+    transactions: &[DbTransaction],
+    last_block: u64,
+    policy: Policy,
+    node_provider: bool,
+) -> Simulation {
+    let mut pool = Pool { policy, lots: VecDeque::new() };
+    let mut simulation = Simulation::default();
+
+    for tx in transactions.iter().filter(|tx| tx.id <= last_block) {
+        let amount = tx.amount.unwrap_or(0);
+        let fee = tx.fee.unwrap_or(0);
+        let incoming = tx.to_account.as_deref() == Some(account);
+        let outgoing = tx.from_account.as_deref() == Some(account);
+
+        match tx.operation_type.as_str() {
+            "Mint" if incoming => pool.add(Origin::Mint(classify_mint(tx.timestamp, node_provider)), amount),
+            "Transfer" if outgoing && incoming => {
+                pool.take(fee);
+            }
+            "Transfer" if incoming => {
+                let from = tx.from_account.clone().unwrap_or_default();
+                pool.add(Origin::Transfer { from, block: tx.id }, amount);
+            }
+            "Transfer" | "Burn" if outgoing => {
+                simulation.outflows.insert(tx.id, pool.take(amount));
+                pool.take(fee);
+            }
+            "Approve" if outgoing => {
+                pool.take(fee);
+            }
+            _ => {}
+        }
+    }
+
+    simulation.balance = pool.total();
+    simulation.holdings = pool.lots.into_iter().collect();
+    // Poison credits every origin it has ever seen with the whole balance
+    if policy == Policy::Poison {
+        simulation.holdings.iter_mut().for_each(|(_, amount)| *amount = simulation.balance);
+    }
+
+    simulation
+}
+
+/// Recursively attributes compositions back to their sources
+pub struct Attributor<F: FnMut(&str) -> Result<Vec<DbTransaction>>> {
+    policy: Policy,
+    hop_limit: usize,
+    /// Amounts below this are not followed further
+    min_amount: u64,
+    last_block: u64,
+    load_transactions: F,
+    labels: HashMap<String, (String, Type)>,
+    simulations: HashMap<String, Simulation>,
+}
+
+impl<F: FnMut(&str) -> Result<Vec<DbTransaction>>> Attributor<F> {
+    pub fn new(policy: Policy, hop_limit: usize, min_amount: u64, last_block: u64, load_transactions: F) -> Self {
+        Self {
+            policy,
+            hop_limit,
+            min_amount,
+            last_block,
+            load_transactions,
+            labels: label_map(),
+            simulations: HashMap::new(),
+        }
+    }
+
+    fn simulation(&mut self, account: &str) -> Result<&Simulation> {
+        if !self.simulations.contains_key(account) {
+            let transactions = (self.load_transactions)(account)?;
+            let node_provider = self.labels.get(account).is_some_and(|(_, ty)| *ty == Type::NodeProvider);
+            let simulation = simulate(account, &transactions, self.last_block, self.policy, node_provider);
+            self.simulations.insert(account.to_string(), simulation);
+        }
+        Ok(&self.simulations[account])
+    }
+
+    /// Attribute the balance `account` holds at `last_block`, returned alongside the sources
+    pub fn attribute_balance(&mut self, account: &str) -> Result<(u64, BTreeMap<Source, u64>)> {
+        let simulation = self.simulation(account)?;
+        let (balance, holdings) = (simulation.balance, simulation.holdings.clone());
+        let mut sources = BTreeMap::new();
+        self.attribute(&holdings, 0, &mut sources)?;
+        Ok((balance, sources))
+    }
+
+    fn attribute(
+        &mut self,
+        composition: &[(Origin, u64)],
+        depth: usize,
+        sources: &mut BTreeMap<Source, u64>,
+    ) -> Result<()> {
+        for (origin, amount) in composition {
+            let (from, block) = match origin {
+                Origin::Mint(category) => {
+                    *sources.entry(Source::Mint { category: *category }).or_insert(0) += amount;
+                    continue;
+                }
+                Origin::Unknown => {
+                    *sources.entry(Source::Unexplained).or_insert(0) += amount;
+                    continue;
+                }
+                Origin::Transfer { from, block } => (from, *block),
+            };
+
+            if let Some((name, ty)) = self.labels.get(from) {
+                let source = Source::Entity { name: name.clone(), category: ty.to_string() };
+                *sources.entry(source).or_insert(0) += amount;
+                continue;
+            }
+            if depth + 1 >= self.hop_limit || *amount < self.min_amount {
+                *sources.entry(Source::Unlabelled { account: from.clone() }).or_insert(0) += amount;
+                continue;
+            }
+
+            // Scale the sender's outflow composition down to the part that reached us
+            let outflow = self.simulation(from)?.outflows.get(&block).cloned().unwrap_or_default();
+            let outflow_total: u64 = outflow.iter().map(|(_, part)| part).sum::<u64>().max(1);
+            let scaled: Vec<(Origin, u64)> = outflow
+                .into_iter()
+                .map(|(origin, part)| {
+                    let part = if self.policy == Policy::Poison {
+                        (*amount).min(part)
+                    } else {
+                        (part as u128 * *amount as u128 / outflow_total as u128) as u64
+                    };
+                    (origin, part)
+                })
+                .collect();
+
+            self.attribute(&scaled, depth + 1, sources)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttributedSource {
+    #[serde(flatten)]
+    pub source: Source,
+    pub amount_e8s: u64,
+    pub share: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttributionReport {
+    pub account: String,
+    pub block: u64,
+    pub policy: Policy,
+    pub hop_limit: usize,
+    pub balance_e8s: u64,
+    pub minted_e8s: u64,
+    pub labelled_e8s: u64,
+    pub unlabelled_e8s: u64,
+    pub unexplained_e8s: u64,
+    pub sources: Vec<AttributedSource>,
+}
+
+fn build_report(
+    account: &str,
+    block: u64,
+    policy: Policy,
+    hop_limit: usize,
+    balance: u64,
+    sources: BTreeMap<Source, u64>,
+) -> AttributionReport {
+    let total = |f: fn(&Source) -> bool| sources.iter().filter(|(s, _)| f(s)).map(|(_, a)| a).sum::<u64>();
+    let minted_e8s = total(|s| matches!(s, Source::Mint { .. }));
+    let labelled_e8s = total(|s| matches!(s, Source::Entity { .. }));
+    let unlabelled_e8s = total(|s| matches!(s, Source::Unlabelled { .. }));
+    let unexplained_e8s = total(|s| matches!(s, Source::Unexplained));
+    let attributed = (minted_e8s + labelled_e8s + unlabelled_e8s + unexplained_e8s).max(1);
+
+    let mut sources: Vec<AttributedSource> = sources
+        .into_iter()
+        .map(|(source, amount_e8s)| AttributedSource {
+            source,
+            amount_e8s,
+            share: amount_e8s as f64 / attributed as f64,
+        })
+        .collect();
+    sources.sort_by_key(|s| std::cmp::Reverse(s.amount_e8s));
+
+    AttributionReport {
+        account: account.to_string(),
+        block,
+        policy,
+        hop_limit,
+        balance_e8s: balance,
+        minted_e8s,
+        labelled_e8s,
+        unlabelled_e8s,
+        unexplained_e8s,
+        sources,
+    }
+}
+
+/// Run the backward attribution against ledger.db
+pub async fn run_attribution(
+    db_path: &str,
+    account: &str,
+    at: Option<&str>,
+    policy: Policy,
+    hop_limit: usize,
+    min_amount: u64,
+) -> Result<()> {
+    println!("===== SOURCE ATTRIBUTION =====");
+    println!("Account: {}", account);
+    println!("Policy: {:?}, hop limit: {}", policy, hop_limit);
+
+    let db = LedgerDatabase::new(db_path)?;
+    let block = match at {
+        Some(at) => PointInTime::parse(at)?.resolve_block(&db)?,
+        None => db.get_last_block()?,
+    };
+    let Some(block) = block else { bail!("no transactions at or before {:?}", at) };
+    println!("At block: {}", block);
+
+    let mut attributor =
+        Attributor::new(policy, hop_limit, min_amount, block, |account| db.get_account_transactions(account));
+    let (balance, sources) = attributor.attribute_balance(account)?;
+    let report = build_report(account, block, policy, hop_limit, balance, sources);
+
+    println!("\nBalance: {} ICP", report.balance_e8s as f64 / 100_000_000.0);
+    println!("Minted: {} ICP", report.minted_e8s as f64 / 100_000_000.0);
+    println!("Labelled entities: {} ICP", report.labelled_e8s as f64 / 100_000_000.0);
+    println!("Unlabelled sources: {} ICP", report.unlabelled_e8s as f64 / 100_000_000.0);
+    println!("Unexplained: {} ICP", report.unexplained_e8s as f64 / 100_000_000.0);
+
+    println!("\nTop sources:");
+    for source in report.sources.iter().take(20) {
+        let name = match &source.source {
+            Source::Mint { category } => format!("Mint ({:?})", category),
+            Source::Entity { name, category } => format!("{} ({})", name, category),
+            Source::Unlabelled { account } => account[..8].to_string(),
+            Source::Unexplained => "Unexplained".to_string(),
+        };
+        println!("  {} - {} ICP ({:.2}%)", name, source.amount_e8s as f64 / 100_000_000.0, source.share * 100.0);
+    }
+
+    let file_name = format!("./attribution_{}_{}.json", &account[..8.min(account.len())], block);
+    std::fs::write(&file_name, serde_json::to_string_pretty(&report)?)?;
+
+    println!("\nAttribution saved to: {}", file_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger_db::test_transaction;
+
+    fn tx(id: u64, operation_type: &str, from: Option<&str>, to: &str, amount: u64) -> DbTransaction {
+        test_transaction(id, operation_type, from, Some(to), amount)
+    }
+
+    #[test]
+    fn test_backward_attribution() {
+        // m is minted 100 at genesis and sends 40 to a; a also gets 60 from u (who got it from a later mint)
+        let after_genesis = 1_700_000_000 * 1_000_000_000;
+        let ledger = [
+            tx(1, "Mint", None, "m", 100),
+            DbTransaction { timestamp: Some(after_genesis), ..tx(2, "Mint", None, "u", 60) },
+            tx(3, "Transfer", Some("m"), "a", 40),
+            tx(4, "Transfer", Some("u"), "a", 60),
+            tx(5, "Transfer", Some("a"), "x", 50),
+        ];
+        let load = |account: &str| -> Result<Vec<DbTransaction>> {
+            Ok(ledger
+                .iter()
+                .filter(|t| t.from_account.as_deref() == Some(account) || t.to_account.as_deref() == Some(account))
+                .cloned()
+                .collect())
+        };
+
+        let mut fifo = Attributor::new(Policy::Fifo, 3, 0, 5, load);
+        let (balance, sources) = fifo.attribute_balance("a").unwrap();
+        assert_eq!(balance, 50);
+        assert_eq!(sources.get(&Source::Mint { category: MintCategory::NeuronMaturity }), Some(&50));
+        assert_eq!(sources.get(&Source::Mint { category: MintCategory::Genesis }), None);
+
+        let mut mixed = Attributor::new(Policy::ProRata, 3, 0, 5, load);
+        let (_, sources) = mixed.attribute_balance("a").unwrap();
+        assert_eq!(sources.get(&Source::Mint { category: MintCategory::Genesis }), Some(&20));
+        assert_eq!(sources.get(&Source::Mint { category: MintCategory::NeuronMaturity }), Some(&30));
+
+        let mut shallow = Attributor::new(Policy::Fifo, 1, 0, 5, load);
+        let (_, sources) = shallow.attribute_balance("a").unwrap();
+        assert_eq!(sources.get(&Source::Unlabelled { account: "u".to_string() }), Some(&50));
+
+        let mut pro_rata = Attributor::new(Policy::ProRata, 1, 0, 5, load);
+        let (_, sources) = pro_rata.attribute_balance("a").unwrap();
+        assert_eq!(sources.get(&Source::Unlabelled { account: "m".to_string() }), Some(&20));
+        assert_eq!(sources.get(&Source::Unlabelled { account: "u".to_string() }), Some(&30));
+    }
+}
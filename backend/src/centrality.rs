@@ -0,0 +1,334 @@
+// Centrality metrics on the aggregated transfer graph, built either from ledger.db or from a traced NetworkAnalysis
+// Weighted PageRank, (sampled) betweenness, and in/out degree and strength, used to rank hub accounts
+
+use crate::{
+    entities::label_map,
+    ledger_db::LedgerDatabase,
+    network_tracer::NetworkAnalysis,
+    transfer_graph::{GraphFilter, TransferGraph},
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+const DAMPING: f64 = 0.85;
+const MAX_ITERATIONS: usize = 100;
+const TOLERANCE: f64 = 1e-10;
+
+/// Directed graph with one edge per (from, to) pair, weighted by total ICP moved
+pub struct DirectedGraph {
+    pub accounts: Vec<String>,
+    outgoing: Vec<Vec<(usize, f64)>>,
+    incoming: Vec<Vec<(usize, f64)>>,
+}
+
+impl DirectedGraph {
+    pub fn from_transfers<'a>(transfers: impl IntoIterator<Item = (&'a str, &'a str, u64)>) -> Self {
+        let mut index: HashMap<&str, usize> = HashMap::new();
+        let mut accounts = Vec::new();
+        let mut weights: HashMap<(usize, usize), f64> = HashMap::new();
+
+        for (from, to, amount) in transfers {
+            if from == to {
+                continue;
+            }
+            let mut id = |account: &'a str| {
+                *index.entry(account).or_insert_with(|| {
+                    accounts.push(account.to_string());
+                    accounts.len() - 1
+                })
+            };
+            let pair = (id(from), id(to));
+            *weights.entry(pair).or_insert(0.0) += amount as f64 / 100_000_000.0;
+        }
+
+        let mut outgoing = vec![Vec::new(); accounts.len()];
+        let mut incoming = vec![Vec::new(); accounts.len()];
+        let mut edges: Vec<((usize, usize), f64)> = weights.into_iter().collect();
+        edges.sort_by_key(|(pair, _)| *pair);
+        for ((from, to), weight) in edges {
+            outgoing[from].push((to, weight));
+            incoming[to].push((from, weight));
+        }
+
+        Self { accounts, outgoing, incoming }
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+}
+
+/// Weighted PageRank; dangling accounts spread their rank evenly
+pub fn pagerank(graph: &DirectedGraph) -> Vec<f64> {
+    let n = graph.len();
+    if n == 0 {
+        return Vec::new();
+    }
+
+    let out_strength: Vec<f64> = graph.outgoing.iter().map(|edges| edges.iter().map(|(_, w)| w).sum()).collect();
+    let mut rank = vec![1.0 / n as f64; n];
+
+    for _ in 0..MAX_ITERATIONS {
+        // Accounts whose only outflows are zero-amount transfers count as dangling too
+        let dangling: f64 = (0..n).filter(|i| out_strength[*i] <= 0.0).map(|i| rank[i]).sum();
+        let base = (1.0 - DAMPING) / n as f64 + DAMPING * dangling / n as f64;
+
+        let next: Vec<f64> = (0..n)
+            .map(|i| {
+                base + DAMPING
+                    * graph.incoming[i]
+                        .iter()
+                        .filter(|(from, _)| out_strength[*from] > 0.0)
+                        .map(|(from, w)| rank[*from] * w / out_strength[*from])
+                        .sum::<f64>()
+            })
+            .collect();
+
+        let delta: f64 = next.iter().zip(&rank).map(|(a, b)| (a - b).abs()).sum();
+        rank = next;
+        if delta < TOLERANCE {
+            break;
+        }
+    }
+
+    rank
+}
+
+/// Evenly spaced betweenness sources; fewer than `samples` when `n` is not a multiple of the stride
+pub fn sample_sources(n: usize, samples: usize) -> Vec<usize> {
+    if n == 0 || samples == 0 {
+        return Vec::new();
+    }
+    (0..n).step_by(n.div_ceil(samples).max(1)).collect()
+}
+
+/// Hop-count betweenness (Brandes), estimated from the sources picked by `sample_sources`
+///
+/// With `samples >= n` the result is exact.
+pub fn betweenness(graph: &DirectedGraph, samples: usize) -> Vec<f64> {
+    let n = graph.len();
+    let mut centrality = vec![0.0; n];
+    let sources = sample_sources(n, samples);
+    if sources.is_empty() {
+        return centrality;
+    }
+
+    for &source in &sources {
+        let mut stack = Vec::new();
+        let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); n];
+        let mut paths = vec![0.0; n];
+        let mut distance: Vec<i64> = vec![-1; n];
+        paths[source] = 1.0;
+        distance[source] = 0;
+
+        let mut queue = VecDeque::from([source]);
+        while let Some(v) = queue.pop_front() {
+            stack.push(v);
+            for &(w, _) in &graph.outgoing[v] {
+                if distance[w] < 0 {
+                    distance[w] = distance[v] + 1;
+                    queue.push_back(w);
+                }
+                if distance[w] == distance[v] + 1 {
+                    paths[w] += paths[v];
+                    predecessors[w].push(v);
+                }
+            }
+        }
+
+        let mut dependency = vec![0.0; n];
+        while let Some(w) = stack.pop() {
+            for &v in &predecessors[w] {
+                dependency[v] += paths[v] / paths[w] * (1.0 + dependency[w]);
+            }
+            if w != source {
+                centrality[w] += dependency[w];
+            }
+        }
+    }
+
+    let scale = n as f64 / sources.len() as f64;
+    centrality.iter_mut().for_each(|c| *c *= scale);
+    centrality
+}
+
+#[derive(Debug, Serialize)]
+pub struct Hub {
+    /// PageRank position, only set for the top N accounts
+    pub rank: Option<usize>,
+    pub account: String,
+    pub label: Option<String>,
+    pub pagerank: f64,
+    pub betweenness: f64,
+    pub in_degree: usize,
+    pub out_degree: usize,
+    /// Total ICP received over all edges
+    pub in_strength: f64,
+    /// Total ICP sent over all edges
+    pub out_strength: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CentralityReport {
+    pub accounts: usize,
+    pub edges: usize,
+    /// Sources actually used for the betweenness estimate
+    pub betweenness_samples: usize,
+    /// Metrics for every account, ordered by PageRank
+    pub nodes: Vec<Hub>,
+    pub hubs: Vec<String>,
+    pub top_betweenness: Vec<String>,
+    pub top_in_strength: Vec<String>,
+    pub top_out_strength: Vec<String>,
+}
+
+/// Compute every metric for every account and rank the top `top_n` by PageRank
+pub fn build_centrality(graph: &DirectedGraph, samples: usize, top_n: usize) -> CentralityReport {
+    let labels = label_map();
+    let pagerank = pagerank(graph);
+    let betweenness = betweenness(graph, samples);
+
+    let mut nodes: Vec<Hub> = (0..graph.len())
+        .map(|i| Hub {
+            rank: None,
+            account: graph.accounts[i].clone(),
+            label: labels.get(&graph.accounts[i]).map(|(name, ty)| format!("{} ({})", name, ty)),
+            pagerank: pagerank[i],
+            betweenness: betweenness[i],
+            in_degree: graph.incoming[i].len(),
+            out_degree: graph.outgoing[i].len(),
+            in_strength: graph.incoming[i].iter().map(|(_, w)| w).sum(),
+            out_strength: graph.outgoing[i].iter().map(|(_, w)| w).sum(),
+        })
+        .collect();
+
+    let top_by = |hubs: &[Hub], key: fn(&Hub) -> f64| {
+        let mut order: Vec<&Hub> = hubs.iter().collect();
+        order.sort_by(|a, b| key(b).total_cmp(&key(a)));
+        order.iter().take(top_n).map(|h| h.account.clone()).collect::<Vec<_>>()
+    };
+    let top_betweenness = top_by(&nodes, |h| h.betweenness);
+    let top_in_strength = top_by(&nodes, |h| h.in_strength);
+    let top_out_strength = top_by(&nodes, |h| h.out_strength);
+
+    nodes.sort_by(|a, b| b.pagerank.total_cmp(&a.pagerank));
+    for (i, hub) in nodes.iter_mut().take(top_n).enumerate() {
+        hub.rank = Some(i + 1);
+    }
+    let hubs = nodes.iter().take(top_n).map(|h| h.account.clone()).collect();
+
+    CentralityReport {
+        accounts: graph.len(),
+        edges: graph.outgoing.iter().map(|e| e.len()).sum(),
+        betweenness_samples: sample_sources(graph.len(), samples).len(),
+        nodes,
+        hubs,
+        top_betweenness,
+        top_in_strength,
+        top_out_strength,
+    }
+}
+
+/// Compute centrality for a traced network and store each node's PageRank and betweenness
+pub fn assign_centrality(analysis: &mut NetworkAnalysis, samples: usize, top_n: usize) -> CentralityReport {
+    let graph =
+        DirectedGraph::from_transfers(analysis.edges.iter().map(|e| (e.from.as_str(), e.to.as_str(), e.total_amount)));
+    let report = build_centrality(&graph, samples, top_n);
+
+    for hub in &report.nodes {
+        if let Some(node) = analysis.nodes.get_mut(&hub.account) {
+            node.pagerank = Some(hub.pagerank);
+            node.betweenness = Some(hub.betweenness);
+        }
+    }
+
+    report
+}
+
+/// Run the centrality ranking over ledger.db
+pub async fn run_centrality(db_path: &str, filter: GraphFilter, samples: usize, top_n: usize) -> Result<()> {
+    println!("===== CENTRALITY =====");
+    println!("Database: {}", db_path);
+
+    let db = LedgerDatabase::new(db_path)?;
+    let transfers = TransferGraph::load(&db, &filter)?;
+    let graph =
+        DirectedGraph::from_transfers(transfers.edges.iter().map(|e| (e.from.as_str(), e.to.as_str(), e.amount)));
+    println!("Graph: {} accounts", graph.len());
+
+    let report = build_centrality(&graph, samples, top_n);
+
+    println!("\nTop hubs by PageRank:");
+    for hub in report.nodes.iter().take(report.hubs.len().min(20)) {
+        println!(
+            "{}. {} {} - pagerank {:.5}, betweenness {:.0}, in {} / out {}",
+            hub.rank.unwrap_or_default(),
+            &hub.account[..8],
+            hub.label.as_deref().unwrap_or(""),
+            hub.pagerank,
+            hub.betweenness,
+            hub.in_degree,
+            hub.out_degree
+        );
+    }
+
+    let file_name = "./centrality_report.json";
+    std::fs::write(file_name, serde_json::to_string_pretty(&report)?)?;
+
+    println!("\nCentrality report saved to: {}", file_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_centrality_on_a_star() {
+        // Every leaf pays the hub, the hub pays "out"
+        let transfers = [("a", "hub", 100), ("b", "hub", 100), ("c", "hub", 100), ("hub", "out", 300)];
+        let graph = DirectedGraph::from_transfers(transfers.iter().copied());
+        let index = |name: &str| graph.accounts.iter().position(|a| a == name).unwrap();
+
+        let pagerank = pagerank(&graph);
+        assert!((pagerank.iter().sum::<f64>() - 1.0).abs() < 1e-6);
+        assert!(pagerank[index("out")] > pagerank[index("hub")]);
+        assert!(pagerank[index("hub")] > pagerank[index("a")]);
+
+        // a, b and c each reach "out" only through the hub
+        let betweenness = betweenness(&graph, graph.len());
+        assert_eq!(betweenness[index("hub")], 3.0);
+        assert_eq!(betweenness[index("a")], 0.0);
+    }
+
+    #[test]
+    fn test_report_covers_every_account() {
+        let transfers: Vec<(String, String)> = (0..9).map(|i| (format!("n{}", i), format!("n{}", i + 1))).collect();
+        let graph = DirectedGraph::from_transfers(transfers.iter().map(|(a, b)| (a.as_str(), b.as_str(), 100)));
+        assert_eq!(graph.len(), 10);
+
+        // A stride of 2 over 10 accounts only yields 5 sources
+        let report = build_centrality(&graph, 6, 3);
+        assert_eq!(report.betweenness_samples, 5);
+        assert_eq!(report.nodes.len(), 10);
+        assert_eq!(report.hubs.len(), 3);
+        assert_eq!(report.nodes.iter().filter(|h| h.rank.is_some()).count(), 3);
+        assert_eq!(report.nodes[0].rank, Some(1));
+        assert_eq!(report.hubs[0], report.nodes[0].account);
+    }
+
+    #[test]
+    fn test_zero_amount_outflows_are_dangling() {
+        let graph = DirectedGraph::from_transfers([("a", "b", 0), ("c", "a", 100)]);
+        let pagerank = pagerank(&graph);
+        assert!(pagerank.iter().all(|r| r.is_finite()));
+        assert!((pagerank.iter().sum::<f64>() - 1.0).abs() < 1e-6);
+        assert_eq!(build_centrality(&graph, 3, 3).nodes.len(), 3);
+    }
+}
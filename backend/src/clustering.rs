@@ -0,0 +1,507 @@
+// Entity clustering heuristics over ledger.db
+// Proposes groups of accounts that are probably controlled by the same owner, with the evidence
+// for each link, so they can be reviewed and added to addresses.rs
+
+use crate::{
+    entities::label_map,
+    ledger_db::{DbTransaction, LedgerDatabase},
+    replay::LedgerState,
+    Type,
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+// (timestamp, amount, block) of an outgoing leg waiting for its return
+type Leg = (u64, u64, u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Heuristic {
+    SharedFirstFunder,
+    ConsolidationSweep,
+    RoundTrip,
+    TimingCoOccurrence,
+}
+
+impl Heuristic {
+    /// How strongly a single piece of evidence of this kind suggests common control
+    fn weight(&self) -> f64 {
+        match self {
+            Self::SharedFirstFunder => 0.5,
+            Self::ConsolidationSweep => 0.7,
+            Self::RoundTrip => 0.6,
+            Self::TimingCoOccurrence => 0.4,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClusterConfig {
+    /// Transfers below this are ignored by the round-trip and timing heuristics
+    pub min_amount: u64,
+    /// Funders and collectors with more counterparties than this are treated as services
+    pub max_fanout: usize,
+    /// A transfer leaving at most this much behind empties the account
+    pub dust: u64,
+    pub round_trip_window_nanos: u64,
+    /// Relative amount difference allowed between the two legs of a round-trip
+    pub round_trip_tolerance: f64,
+    pub timing_window_nanos: u64,
+    pub min_co_occurrences: usize,
+    /// Recipients paid by more senders than this within the timing window are busy, not coordinated
+    pub max_timing_senders: usize,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            min_amount: 100_000_000,
+            max_fanout: 50,
+            dust: 10_000,
+            round_trip_window_nanos: 7 * 24 * 60 * 60 * NANOS_PER_SECOND,
+            round_trip_tolerance: 0.05,
+            timing_window_nanos: 60 * NANOS_PER_SECOND,
+            min_co_occurrences: 3,
+            max_timing_senders: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Evidence {
+    pub heuristic: Heuristic,
+    pub accounts: Vec<String>,
+    pub blocks: Vec<u64>,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Cluster {
+    pub id: usize,
+    pub accounts: Vec<String>,
+    pub labels: Vec<(String, String)>,
+    pub confidence: f64,
+    pub evidence: Vec<Evidence>,
+}
+
+/// Combine independent evidence: 1 - product of (1 - weight), counting at most three links per heuristic
+pub fn confidence(evidence: &[Evidence]) -> f64 {
+    let mut per_heuristic: HashMap<Heuristic, usize> = HashMap::new();
+    for item in evidence {
+        *per_heuristic.entry(item.heuristic).or_insert(0) += 1;
+    }
+
+    1.0 - per_heuristic.iter().map(|(h, count)| (1.0 - h.weight()).powi((*count).min(3) as i32)).product::<f64>()
+}
+
+/// Union-find over account ids
+pub(crate) struct DisjointSet {
+    parent: Vec<usize>,
+}
+
+impl DisjointSet {
+    pub(crate) fn new(size: usize) -> Self {
+        Self { parent: (0..size).collect() }
+    }
+
+    pub(crate) fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    pub(crate) fn union(&mut self, a: usize, b: usize) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra != rb {
+            self.parent[rb] = ra;
+        }
+    }
+}
+
+/// Collects heuristic signals while streaming the ledger
+pub struct ClusterBuilder {
+    config: ClusterConfig,
+    state: LedgerState,
+    labels: HashMap<String, (String, Type)>,
+    first_funder: HashMap<String, Option<(String, u64)>>,
+    sweeps: HashMap<String, Vec<(String, u64)>>,
+    recent_legs: HashMap<(String, String), VecDeque<Leg>>,
+    recent_senders: HashMap<String, VecDeque<(u64, String, u64)>>,
+    co_occurrences: HashMap<(String, String), Vec<u64>>,
+    last_prune: u64,
+    evidence: Vec<Evidence>,
+}
+
+impl ClusterBuilder {
+    pub fn new(config: ClusterConfig) -> Self {
+        Self {
+            config,
+            state: LedgerState::new(),
+            labels: label_map(),
+            first_funder: HashMap::new(),
+            sweeps: HashMap::new(),
+            recent_legs: HashMap::new(),
+            recent_senders: HashMap::new(),
+            co_occurrences: HashMap::new(),
+            last_prune: 0,
+            evidence: Vec::new(),
+        }
+    }
+
+    /// Services fund and collect for unrelated users, so they never act as a cluster hub
+    fn is_service(&self, account: &str) -> bool {
+        self.labels.get(account).is_some_and(|(_, ty)| matches!(ty, Type::Cex | Type::Defi | Type::Sns))
+    }
+
+    pub fn observe(&mut self, tx: &DbTransaction) {
+        let amount = tx.amount.unwrap_or(0);
+
+        match (tx.operation_type.as_str(), &tx.from_account, &tx.to_account) {
+            ("Transfer", Some(from), Some(to)) if from != to => {
+                self.first_funder.entry(to.clone()).or_insert_with(|| Some((from.clone(), tx.id)));
+
+                let before = self.state.balances.get(from).copied().unwrap_or(0);
+                let after = before - (amount + tx.fee.unwrap_or(0)) as i64;
+                if amount > 0 && after <= self.config.dust as i64 {
+                    self.sweeps.entry(to.clone()).or_default().push((from.clone(), tx.id));
+                }
+
+                if amount >= self.config.min_amount {
+                    let timestamp = tx.timestamp.unwrap_or(0);
+                    self.prune(timestamp);
+                    self.check_round_trip(from, to, amount, timestamp, tx.id);
+                    self.check_timing(from, to, timestamp, tx.id);
+                }
+            }
+            ("Mint", _, Some(to)) => {
+                self.first_funder.entry(to.clone()).or_insert(None);
+            }
+            _ => {}
+        }
+
+        self.state.apply(tx);
+    }
+
+    /// Drop round-trip legs and recent senders that fell out of their windows
+    ///
+    /// Runs once per round-trip window of ledger time, so memory stays bounded by the transfers of
+    /// about two windows rather than the whole ledger.
+    fn prune(&mut self, timestamp: u64) {
+        let round_trip_window = self.config.round_trip_window_nanos;
+        if timestamp.saturating_sub(self.last_prune) <= round_trip_window {
+            return;
+        }
+        self.last_prune = timestamp;
+
+        self.recent_legs.retain(|_, legs| {
+            while legs.front().is_some_and(|(ts, _, _)| timestamp.saturating_sub(*ts) > round_trip_window) {
+                legs.pop_front();
+            }
+            !legs.is_empty()
+        });
+        let timing_window = self.config.timing_window_nanos;
+        self.recent_senders.retain(|_, senders| {
+            senders.back().is_some_and(|(ts, _, _)| timestamp.saturating_sub(*ts) <= timing_window)
+        });
+    }
+
+    /// `from -> to` closing an earlier `to -> from` of a similar amount
+    fn check_round_trip(&mut self, from: &str, to: &str, amount: u64, timestamp: u64, block: u64) {
+        let window = self.config.round_trip_window_nanos;
+
+        if let Some(legs) = self.recent_legs.get_mut(&(to.to_string(), from.to_string())) {
+            while legs.front().is_some_and(|(ts, _, _)| timestamp.saturating_sub(*ts) > window) {
+                legs.pop_front();
+            }
+            let similar = legs.iter().position(|(_, out_amount, _)| {
+                (*out_amount as f64 - amount as f64).abs() <= *out_amount as f64 * self.config.round_trip_tolerance
+            });
+            if let Some(i) = similar {
+                let (out_ts, out_amount, out_block) = legs.remove(i).unwrap();
+                self.evidence.push(Evidence {
+                    heuristic: Heuristic::RoundTrip,
+                    accounts: vec![to.to_string(), from.to_string()],
+                    blocks: vec![out_block, block],
+                    detail: format!(
+                        "{} ICP out and {} ICP back after {} hours",
+                        out_amount as f64 / 100_000_000.0,
+                        amount as f64 / 100_000_000.0,
+                        timestamp.saturating_sub(out_ts) / (3600 * NANOS_PER_SECOND)
+                    ),
+                });
+                return;
+            }
+        }
+
+        let legs = self.recent_legs.entry((from.to_string(), to.to_string())).or_default();
+        legs.push_back((timestamp, amount, block));
+    }
+
+    /// Different senders paying the same recipient within the timing window
+    fn check_timing(&mut self, from: &str, to: &str, timestamp: u64, block: u64) {
+        if self.labels.contains_key(to) {
+            return;
+        }
+        let window = self.config.timing_window_nanos;
+
+        let senders = self.recent_senders.entry(to.to_string()).or_default();
+        while senders.front().is_some_and(|(ts, _, _)| timestamp.saturating_sub(*ts) > window) {
+            senders.pop_front();
+        }
+        if senders.len() >= self.config.max_timing_senders {
+            senders.pop_front();
+            senders.push_back((timestamp, from.to_string(), block));
+            return;
+        }
+        for (_, other, other_block) in senders.iter() {
+            if other != from {
+                let pair = if other.as_str() < from {
+                    (other.clone(), from.to_string())
+                } else {
+                    (from.to_string(), other.clone())
+                };
+                let blocks = self.co_occurrences.entry(pair).or_default();
+                blocks.push(*other_block);
+                blocks.push(block);
+            }
+        }
+        senders.push_back((timestamp, from.to_string(), block));
+    }
+
+    /// Turn the collected signals into evidence and group linked accounts
+    pub fn finish(mut self) -> Vec<Cluster> {
+        // Shared first funder
+        let mut funded: HashMap<String, Vec<(String, u64)>> = HashMap::new();
+        for (account, funder) in self.first_funder.drain() {
+            if let Some((funder, block)) = funder {
+                funded.entry(funder).or_default().push((account, block));
+            }
+        }
+        for (funder, mut children) in funded {
+            if children.len() < 2 || children.len() > self.config.max_fanout || self.is_service(&funder) {
+                continue;
+            }
+            children.sort_by_key(|(_, block)| *block);
+            self.evidence.push(Evidence {
+                heuristic: Heuristic::SharedFirstFunder,
+                accounts: children.iter().map(|(a, _)| a.clone()).collect(),
+                blocks: children.iter().map(|(_, b)| *b).collect(),
+                detail: format!("{} accounts first funded by {}", children.len(), funder),
+            });
+        }
+
+        // Consolidation sweeps
+        for (collector, sweeps) in std::mem::take(&mut self.sweeps) {
+            let sweepers: BTreeSet<&String> = sweeps.iter().map(|(a, _)| a).collect();
+            if sweepers.len() < 2 || sweepers.len() > self.config.max_fanout || self.is_service(&collector) {
+                continue;
+            }
+            self.evidence.push(Evidence {
+                heuristic: Heuristic::ConsolidationSweep,
+                accounts: std::iter::once(collector.clone()).chain(sweepers.iter().map(|a| a.to_string())).collect(),
+                blocks: sweeps.iter().map(|(_, b)| *b).collect(),
+                detail: format!("{} accounts emptied into {}", sweepers.len(), collector),
+            });
+        }
+
+        // Timing co-occurrence
+        for ((a, b), blocks) in self.co_occurrences.drain() {
+            let occurrences = blocks.len() / 2;
+            if occurrences >= self.config.min_co_occurrences {
+                self.evidence.push(Evidence {
+                    heuristic: Heuristic::TimingCoOccurrence,
+                    accounts: vec![a, b],
+                    blocks: blocks.into_iter().take(10).collect(),
+                    detail: format!("paid the same recipient within the window {} times", occurrences),
+                });
+            }
+        }
+
+        build_clusters(self.evidence, &self.labels)
+    }
+}
+
+fn build_clusters(evidence: Vec<Evidence>, labels: &HashMap<String, (String, Type)>) -> Vec<Cluster> {
+    let mut ids: HashMap<&str, usize> = HashMap::new();
+    for item in &evidence {
+        for account in &item.accounts {
+            let next = ids.len();
+            ids.entry(account.as_str()).or_insert(next);
+        }
+    }
+
+    let mut set = DisjointSet::new(ids.len());
+    for item in &evidence {
+        for pair in item.accounts.windows(2) {
+            set.union(ids[pair[0].as_str()], ids[pair[1].as_str()]);
+        }
+    }
+
+    let mut members: HashMap<usize, BTreeSet<String>> = HashMap::new();
+    for (account, id) in &ids {
+        members.entry(set.find(*id)).or_default().insert(account.to_string());
+    }
+    let mut grouped: HashMap<usize, Vec<Evidence>> = HashMap::new();
+    for item in &evidence {
+        let root = set.find(ids[item.accounts[0].as_str()]);
+        grouped.entry(root).or_default().push(item.clone());
+    }
+
+    let mut clusters: Vec<Cluster> = members
+        .into_iter()
+        .map(|(root, accounts)| {
+            let evidence = grouped.remove(&root).unwrap_or_default();
+            let labels = accounts
+                .iter()
+                .filter_map(|a| labels.get(a).map(|(name, ty)| (a.clone(), format!("{} ({})", name, ty))))
+                .collect();
+            Cluster {
+                id: 0,
+                accounts: accounts.into_iter().collect(),
+                labels,
+                confidence: confidence(&evidence),
+                evidence,
+            }
+        })
+        .collect();
+
+    clusters.sort_by(|a, b| {
+        b.confidence.partial_cmp(&a.confidence).unwrap().then_with(|| b.accounts.len().cmp(&a.accounts.len()))
+    });
+    for (i, cluster) in clusters.iter_mut().enumerate() {
+        cluster.id = i + 1;
+    }
+
+    clusters
+}
+
+/// Render clusters as entries for a `&[(&str, &[&str])]` list in addresses.rs
+pub fn to_address_entries(clusters: &[Cluster]) -> String {
+    let mut out = String::from("// Candidate entries proposed by the clustering heuristics, review before use\n");
+
+    for cluster in clusters {
+        let heuristics: BTreeSet<String> = cluster.evidence.iter().map(|e| format!("{:?}", e.heuristic)).collect();
+        let name = match cluster.labels.first() {
+            Some((_, label)) => format!("Cluster {} ({})", cluster.id, label),
+            None => format!("Cluster {}", cluster.id),
+        };
+
+        out.push_str(&format!(
+            "// confidence {:.2}: {}\n",
+            cluster.confidence,
+            heuristics.into_iter().collect::<Vec<_>>().join(", ")
+        ));
+        out.push_str(&format!("(\n    \"{}\",\n    &[\n", name));
+        for account in &cluster.accounts {
+            out.push_str(&format!("        \"{}\",\n", account));
+        }
+        out.push_str("    ],\n),\n");
+    }
+
+    out
+}
+
+/// Run the clustering heuristics over ledger.db
+pub async fn run_clustering(db_path: &str, config: ClusterConfig, min_confidence: f64) -> Result<()> {
+    println!("===== ENTITY CLUSTERING =====");
+    println!("Database: {}", db_path);
+
+    let db = LedgerDatabase::new(db_path)?;
+    let mut builder = ClusterBuilder::new(config);
+    db.for_each_transaction(None, |tx| {
+        builder.observe(&tx);
+        Ok(())
+    })?;
+
+    let clusters: Vec<Cluster> = builder.finish().into_iter().filter(|c| c.confidence >= min_confidence).collect();
+    let known: HashSet<&String> = clusters.iter().flat_map(|c| c.labels.iter().map(|(a, _)| a)).collect();
+
+    println!("\nClusters with confidence >= {:.2}: {}", min_confidence, clusters.len());
+    println!("Clusters touching labelled accounts: {}", clusters.iter().filter(|c| !c.labels.is_empty()).count());
+    println!("Labelled accounts covered: {}", known.len());
+
+    for cluster in clusters.iter().take(20) {
+        println!(
+            "  Cluster {}: {} accounts, confidence {:.2}, {} pieces of evidence",
+            cluster.id,
+            cluster.accounts.len(),
+            cluster.confidence,
+            cluster.evidence.len()
+        );
+    }
+
+    let file_name = "./clusters.json";
+    std::fs::write(file_name, serde_json::to_string_pretty(&clusters)?)?;
+    let entries_file = "./cluster_candidates.rs";
+    std::fs::write(entries_file, to_address_entries(&clusters))?;
+
+    println!("\nClusters saved to: {}", file_name);
+    println!("Candidate addresses.rs entries saved to: {}", entries_file);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger_db::test_transaction;
+
+    fn tx(id: u64, operation_type: &str, from: Option<&str>, to: &str, amount: u64, seconds: u64) -> DbTransaction {
+        DbTransaction {
+            timestamp: Some(seconds * NANOS_PER_SECOND),
+            ..test_transaction(id, operation_type, from, Some(to), amount)
+        }
+    }
+
+    #[test]
+    fn test_clustering_heuristics() {
+        let icp = 100_000_000;
+        let mut builder = ClusterBuilder::new(ClusterConfig::default());
+        for t in [
+            tx(1, "Mint", None, "f", 100 * icp, 0),
+            tx(2, "Transfer", Some("f"), "a", 10 * icp, 10),
+            tx(3, "Transfer", Some("f"), "b", 10 * icp, 20),
+            // both emptied into c
+            tx(4, "Transfer", Some("a"), "c", 10 * icp, 1000),
+            tx(5, "Transfer", Some("b"), "c", 10 * icp, 2000),
+            // round-trip between x and y
+            tx(6, "Mint", None, "x", 50 * icp, 3000),
+            tx(7, "Transfer", Some("x"), "y", 20 * icp, 3100),
+            tx(8, "Transfer", Some("y"), "x", 20 * icp, 3200),
+        ] {
+            builder.observe(&t);
+        }
+
+        let clusters = builder.finish();
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].accounts, vec!["a", "b", "c"]);
+        assert_eq!(clusters[1].accounts, vec!["x", "y"]);
+        assert!((clusters[0].confidence - (1.0 - 0.5 * 0.3)).abs() < 1e-9);
+        assert!(to_address_entries(&clusters).contains("\"Cluster 2\""));
+    }
+
+    #[test]
+    fn test_windows_are_bounded() {
+        let icp = 100_000_000;
+        let config = ClusterConfig { max_timing_senders: 3, min_co_occurrences: 1, ..ClusterConfig::default() };
+        let mut builder = ClusterBuilder::new(config);
+        // A busy recipient paid by many senders in the same minute
+        for i in 0..10 {
+            let sender = format!("s{}", i);
+            builder.observe(&tx(i, "Mint", None, &sender, 10 * icp, i));
+            builder.observe(&tx(100 + i, "Transfer", Some(&sender), "busy", 2 * icp, 10 + i));
+        }
+        assert_eq!(builder.co_occurrences.values().map(|b| b.len() / 2).sum::<usize>(), 3);
+        assert!(builder.recent_senders["busy"].len() <= 3);
+
+        // A transfer weeks later clears the expired legs and senders
+        builder.observe(&tx(200, "Transfer", Some("s0"), "late", 2 * icp, 30 * 24 * 3600));
+        assert_eq!(builder.recent_legs.len(), 1);
+        assert_eq!(builder.recent_senders.len(), 1);
+    }
+}
@@ -0,0 +1,305 @@
+// Community detection on the transfer graph
+// Louvain modularity optimisation over an undirected graph weighted by transferred ICP,
+// built either from ledger.db or from a traced NetworkAnalysis
+
+use crate::{
+    entities::label_map,
+    ledger_db::LedgerDatabase,
+    network_tracer::NetworkAnalysis,
+    transfer_graph::{GraphFilter, TransferGraph},
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Undirected weighted graph; self-loop weights are stored doubled so `k_i` is the row sum
+pub struct WeightedGraph {
+    pub accounts: Vec<String>,
+    adjacency: Vec<HashMap<usize, f64>>,
+}
+
+impl WeightedGraph {
+    /// Build from (from, to, amount) triples; direction is dropped and parallel edges are summed
+    pub fn from_transfers<'a>(transfers: impl IntoIterator<Item = (&'a str, &'a str, u64)>) -> Self {
+        let mut index: HashMap<&str, usize> = HashMap::new();
+        let mut accounts = Vec::new();
+        let mut adjacency: Vec<HashMap<usize, f64>> = Vec::new();
+
+        for (from, to, amount) in transfers {
+            if from == to {
+                continue;
+            }
+            let mut id = |account: &'a str| {
+                *index.entry(account).or_insert_with(|| {
+                    accounts.push(account.to_string());
+                    adjacency.push(HashMap::new());
+                    accounts.len() - 1
+                })
+            };
+            let (a, b) = (id(from), id(to));
+            let weight = amount as f64 / 100_000_000.0;
+            *adjacency[a].entry(b).or_insert(0.0) += weight;
+            *adjacency[b].entry(a).or_insert(0.0) += weight;
+        }
+
+        Self { accounts, adjacency }
+    }
+}
+
+/// One level of Louvain: move nodes between communities until modularity stops improving
+fn local_moves(adjacency: &[HashMap<usize, f64>], degree: &[f64], total: f64) -> (Vec<usize>, bool) {
+    let n = adjacency.len();
+    let mut community: Vec<usize> = (0..n).collect();
+    let mut community_degree = degree.to_vec();
+    let mut moved_any = false;
+
+    loop {
+        let mut moved = false;
+        for node in 0..n {
+            let current = community[node];
+
+            let mut links: HashMap<usize, f64> = HashMap::new();
+            for (&neighbour, &weight) in &adjacency[node] {
+                if neighbour != node {
+                    *links.entry(community[neighbour]).or_insert(0.0) += weight;
+                }
+            }
+
+            community_degree[current] -= degree[node];
+            let gain = |c: usize, links_to: f64| links_to - community_degree[c] * degree[node] / total;
+
+            let mut best = current;
+            let mut best_gain = gain(current, links.get(&current).copied().unwrap_or(0.0));
+            for (&c, &links_to) in &links {
+                let g = gain(c, links_to);
+                if g > best_gain + 1e-12 {
+                    best = c;
+                    best_gain = g;
+                }
+            }
+
+            community_degree[best] += degree[node];
+            if best != current {
+                community[node] = best;
+                moved = true;
+                moved_any = true;
+            }
+        }
+        if !moved {
+            break;
+        }
+    }
+
+    (community, moved_any)
+}
+
+/// Louvain community detection; returns a community per account and the final modularity
+pub fn louvain(graph: &WeightedGraph) -> (Vec<usize>, f64) {
+    let mut assignment: Vec<usize> = (0..graph.accounts.len()).collect();
+    let mut adjacency = graph.adjacency.clone();
+
+    loop {
+        let degree: Vec<f64> = adjacency.iter().map(|row| row.values().sum()).collect();
+        let total: f64 = degree.iter().sum();
+        if total == 0.0 {
+            break;
+        }
+
+        let (community, moved) = local_moves(&adjacency, &degree, total);
+        if !moved {
+            break;
+        }
+
+        // Renumber the communities and collapse each into a single node
+        let mut renumber: HashMap<usize, usize> = HashMap::new();
+        for c in &community {
+            let next = renumber.len();
+            renumber.entry(*c).or_insert(next);
+        }
+        let mut aggregated: Vec<HashMap<usize, f64>> = vec![HashMap::new(); renumber.len()];
+        for (node, row) in adjacency.iter().enumerate() {
+            let a = renumber[&community[node]];
+            for (&neighbour, &weight) in row {
+                *aggregated[a].entry(renumber[&community[neighbour]]).or_insert(0.0) += weight;
+            }
+        }
+
+        for slot in assignment.iter_mut() {
+            *slot = renumber[&community[*slot]];
+        }
+        adjacency = aggregated;
+    }
+
+    let q = modularity(&graph.adjacency, &assignment);
+    (assignment, q)
+}
+
+/// Newman modularity of a partition
+pub fn modularity(adjacency: &[HashMap<usize, f64>], community: &[usize]) -> f64 {
+    let total: f64 = adjacency.iter().flat_map(|row| row.values()).sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+
+    let mut internal: HashMap<usize, f64> = HashMap::new();
+    let mut degree: HashMap<usize, f64> = HashMap::new();
+    for (node, row) in adjacency.iter().enumerate() {
+        for (&neighbour, &weight) in row {
+            *degree.entry(community[node]).or_insert(0.0) += weight;
+            if community[node] == community[neighbour] {
+                *internal.entry(community[node]).or_insert(0.0) += weight;
+            }
+        }
+    }
+
+    degree.iter().map(|(c, d)| internal.get(c).copied().unwrap_or(0.0) / total - (d / total).powi(2)).sum()
+}
+
+/// Relabel communities so that 0 is the largest
+fn by_size(assignment: &[usize]) -> Vec<usize> {
+    let mut sizes: HashMap<usize, usize> = HashMap::new();
+    for c in assignment {
+        *sizes.entry(*c).or_insert(0) += 1;
+    }
+    let mut order: Vec<(usize, usize)> = sizes.into_iter().collect();
+    order.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let rank: HashMap<usize, usize> = order.iter().enumerate().map(|(i, (c, _))| (*c, i)).collect();
+
+    assignment.iter().map(|c| rank[c]).collect()
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CommunitySummary {
+    pub id: usize,
+    pub size: usize,
+    pub accounts: Vec<String>,
+    pub labels: Vec<String>,
+    pub internal_volume_e8s: u64,
+    pub inflow_e8s: u64,
+    pub outflow_e8s: u64,
+    /// Only known when built from a NetworkAnalysis
+    pub total_balance_e8s: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommunityReport {
+    pub modularity: f64,
+    pub communities: Vec<CommunitySummary>,
+    /// Community id per account, for colouring the graph
+    pub assignments: HashMap<String, usize>,
+}
+
+fn build_report<'a>(
+    graph: &WeightedGraph,
+    transfers: impl IntoIterator<Item = (&'a str, &'a str, u64)>,
+) -> CommunityReport {
+    let (raw, modularity) = louvain(graph);
+    let community = by_size(&raw);
+    let labels = label_map();
+
+    let assignments: HashMap<String, usize> = graph.accounts.iter().cloned().zip(community.iter().copied()).collect();
+    let count = community.iter().max().map(|c| c + 1).unwrap_or(0);
+    let mut communities: Vec<CommunitySummary> =
+        (0..count).map(|id| CommunitySummary { id, ..Default::default() }).collect();
+
+    for (account, c) in graph.accounts.iter().zip(&community) {
+        let summary = &mut communities[*c];
+        summary.size += 1;
+        summary.accounts.push(account.clone());
+        if let Some((name, ty)) = labels.get(account) {
+            summary.labels.push(format!("{} ({})", name, ty));
+        }
+    }
+    for (from, to, amount) in transfers {
+        match (assignments.get(from), assignments.get(to)) {
+            (Some(a), Some(b)) if a == b => communities[*a].internal_volume_e8s += amount,
+            (Some(a), Some(b)) => {
+                communities[*a].outflow_e8s += amount;
+                communities[*b].inflow_e8s += amount;
+            }
+            _ => {}
+        }
+    }
+
+    CommunityReport { modularity, communities, assignments }
+}
+
+/// Detect communities in a traced network and store each node's community id
+pub fn assign_communities(analysis: &mut NetworkAnalysis) -> CommunityReport {
+    let transfers = || analysis.edges.iter().map(|e| (e.from.as_str(), e.to.as_str(), e.total_amount));
+    let graph = WeightedGraph::from_transfers(transfers());
+    let mut report = build_report(&graph, transfers());
+
+    for summary in &mut report.communities {
+        let balance = summary.accounts.iter().filter_map(|a| analysis.nodes.get(a)).map(|n| n.balance).sum();
+        summary.total_balance_e8s = Some(balance);
+    }
+    for (address, node) in analysis.nodes.iter_mut() {
+        node.community = report.assignments.get(address).copied();
+    }
+
+    report
+}
+
+/// Run community detection over every transfer in ledger.db
+pub async fn run_communities(db_path: &str, filter: GraphFilter) -> Result<()> {
+    println!("===== COMMUNITY DETECTION =====");
+    println!("Database: {}", db_path);
+    println!("Min amount: {} ICP", filter.min_amount as f64 / 100_000_000.0);
+
+    let db = LedgerDatabase::new(db_path)?;
+    let transfer_graph = TransferGraph::load(&db, &filter)?;
+    let transfers = || transfer_graph.edges.iter().map(|e| (e.from.as_str(), e.to.as_str(), e.amount));
+
+    let graph = WeightedGraph::from_transfers(transfers());
+    println!("Graph: {} accounts, {} transfers", graph.accounts.len(), transfer_graph.edges.len());
+
+    let report = build_report(&graph, transfers());
+
+    println!("\nModularity: {:.4}", report.modularity);
+    println!("Communities: {}", report.communities.len());
+    for summary in report.communities.iter().take(10) {
+        println!(
+            "  #{}: {} accounts, internal {} ICP, labels: {}",
+            summary.id,
+            summary.size,
+            summary.internal_volume_e8s as f64 / 100_000_000.0,
+            summary.labels.iter().take(3).cloned().collect::<Vec<_>>().join(", ")
+        );
+    }
+
+    let file_name = "../graph/public/communities.json";
+    std::fs::write(file_name, serde_json::to_string_pretty(&report)?)?;
+
+    println!("\nCommunities saved to: {}", file_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_louvain_splits_two_cliques() {
+        let icp = 100_000_000;
+        let mut transfers = Vec::new();
+        for group in [["a", "b", "c", "d"], ["w", "x", "y", "z"]] {
+            for i in 0..4 {
+                for j in i + 1..4 {
+                    transfers.push((group[i], group[j], 10 * icp));
+                }
+            }
+        }
+        transfers.push(("d", "w", icp));
+
+        let graph = WeightedGraph::from_transfers(transfers.iter().copied());
+        let (community, q) = louvain(&graph);
+
+        let of = |name: &str| community[graph.accounts.iter().position(|a| a == name).unwrap()];
+        assert_eq!(of("a"), of("d"));
+        assert_eq!(of("w"), of("z"));
+        assert_ne!(of("a"), of("z"));
+        assert!(q > 0.4);
+    }
+}
@@ -0,0 +1,364 @@
+// Coordinated-timing detection over ledger.db
+// Finds sets of accounts that send transactions within seconds of each other far more often than
+// their activity levels would produce by chance, which suggests a single operator behind them
+
+use crate::{
+    clustering::DisjointSet,
+    entities::label_map,
+    ledger_db::{DbTransaction, LedgerDatabase, NANOS_PER_DAY},
+    transfer_graph::TimeWindow,
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+#[derive(Debug, Clone)]
+pub struct CoordinationConfig {
+    /// Two actions this close together count as a co-occurrence
+    pub window_nanos: u64,
+    /// Transactions below this are ignored
+    pub min_amount: u64,
+    pub min_co_occurrences: usize,
+    /// Largest p-value reported after correcting for the number of pairs tested
+    pub max_p_value: f64,
+    /// Accounts with more actions than this are treated as services
+    pub max_events: usize,
+    /// Windows with more actions than this are busy periods rather than coordination
+    pub max_burst: usize,
+    pub window: TimeWindow,
+}
+
+impl Default for CoordinationConfig {
+    fn default() -> Self {
+        Self {
+            window_nanos: 30 * NANOS_PER_SECOND,
+            min_amount: 100_000_000,
+            min_co_occurrences: 3,
+            max_p_value: 0.01,
+            max_events: 5_000,
+            max_burst: 20,
+            window: TimeWindow::default(),
+        }
+    }
+}
+
+/// An action taken by an account: (timestamp, account id, block)
+type Event = (u64, usize, u64);
+
+#[derive(Debug, Default)]
+struct PairStats {
+    co_occurrences: usize,
+    last_timestamp: u64,
+    examples: Vec<(u64, u64)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CoordinatedPair {
+    pub accounts: (String, String),
+    /// Actions taken by each account
+    pub events: (usize, usize),
+    pub co_occurrences: usize,
+    /// Co-occurrences expected if both accounts acted independently at their average rates
+    pub expected: f64,
+    pub lift: f64,
+    /// Poisson tail probability, corrected for the number of pairs tested
+    pub p_value: f64,
+    /// (block, block) pairs of matching actions
+    pub example_blocks: Vec<(u64, u64)>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CoordinatedSet {
+    pub accounts: Vec<String>,
+    pub labels: Vec<(String, String)>,
+    pub co_occurrences: usize,
+    pub min_p_value: f64,
+    pub pairs: Vec<CoordinatedPair>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CoordinationReport {
+    pub events_scanned: usize,
+    pub span_days: f64,
+    pub window_seconds: u64,
+    pub pairs_tested: usize,
+    pub sets: Vec<CoordinatedSet>,
+}
+
+/// P(X >= k) for X ~ Poisson(lambda)
+pub fn poisson_tail(k: usize, lambda: f64) -> f64 {
+    if k == 0 {
+        return 1.0;
+    }
+    if lambda <= 0.0 {
+        return 0.0;
+    }
+
+    // Below the mean the tail is large, so sum the k lower terms instead; the upper sum would
+    // start far from the peak and stop before reaching the bulk of the distribution
+    if k as f64 <= lambda {
+        let mut ln_term = -lambda;
+        let mut lower = 0.0;
+        for i in 0..k {
+            lower += ln_term.exp();
+            ln_term += lambda.ln() - ((i + 1) as f64).ln();
+        }
+        return (1.0 - lower).clamp(0.0, 1.0);
+    }
+
+    let ln_factorial: f64 = (2..=k).map(|i| (i as f64).ln()).sum();
+    let mut ln_term = -lambda + k as f64 * lambda.ln() - ln_factorial;
+    let mut sum = 0.0;
+    for i in k..k + 10_000 {
+        let term = ln_term.exp();
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+        ln_term += lambda.ln() - ((i + 1) as f64).ln();
+    }
+    sum.min(1.0)
+}
+
+/// Collects the actions (transactions an account initiated) while streaming the ledger
+#[derive(Default)]
+pub struct TimingCollector {
+    ids: HashMap<String, usize>,
+    accounts: Vec<String>,
+    events: Vec<Event>,
+}
+
+impl TimingCollector {
+    pub fn observe(&mut self, tx: &DbTransaction, config: &CoordinationConfig) {
+        let (Some(from), Some(timestamp)) = (&tx.from_account, tx.timestamp) else { return };
+        if tx.operation_type == "Mint"
+            || tx.amount.unwrap_or(0) < config.min_amount
+            || !config.window.contains(timestamp)
+        {
+            return;
+        }
+
+        let next = self.accounts.len();
+        let id = *self.ids.entry(from.clone()).or_insert(next);
+        if id == next {
+            self.accounts.push(from.clone());
+        }
+        self.events.push((timestamp, id, tx.id));
+    }
+
+    pub fn finish(mut self, config: &CoordinationConfig) -> CoordinationReport {
+        self.events.sort_unstable();
+
+        let mut counts = vec![0usize; self.accounts.len()];
+        for (_, id, _) in &self.events {
+            counts[*id] += 1;
+        }
+        let active: Vec<Event> =
+            self.events.iter().copied().filter(|(_, id, _)| counts[*id] <= config.max_events).collect();
+
+        let span = match (active.first(), active.last()) {
+            (Some(first), Some(last)) => (last.0 - first.0).max(config.window_nanos),
+            _ => config.window_nanos,
+        };
+
+        let mut pairs: HashMap<(usize, usize), PairStats> = HashMap::new();
+        for (i, &(timestamp, account, block)) in active.iter().enumerate() {
+            let end = active[i + 1..].partition_point(|e| e.0 <= timestamp + config.window_nanos) + i + 1;
+            if end - i > config.max_burst {
+                continue;
+            }
+            for &(other_timestamp, other, other_block) in &active[i + 1..end] {
+                if other == account {
+                    continue;
+                }
+                let (key, blocks) = if account < other {
+                    ((account, other), (block, other_block))
+                } else {
+                    ((other, account), (other_block, block))
+                };
+                let stats = pairs.entry(key).or_default();
+                // Actions in one burst count once
+                if stats.co_occurrences > 0 && timestamp <= stats.last_timestamp + config.window_nanos {
+                    continue;
+                }
+                stats.co_occurrences += 1;
+                stats.last_timestamp = other_timestamp;
+                if stats.examples.len() < 5 {
+                    stats.examples.push(blocks);
+                }
+            }
+        }
+
+        let pairs_tested = pairs.len();
+        let mut significant = Vec::new();
+        for ((a, b), stats) in pairs {
+            if stats.co_occurrences < config.min_co_occurrences {
+                continue;
+            }
+            // Chance of one of b's actions landing within the window either side of one of a's
+            let expected = counts[a] as f64 * counts[b] as f64 * (2 * config.window_nanos) as f64 / span as f64;
+            let p_value = (poisson_tail(stats.co_occurrences, expected) * pairs_tested as f64).min(1.0);
+            if p_value > config.max_p_value {
+                continue;
+            }
+            significant.push((
+                a,
+                b,
+                CoordinatedPair {
+                    accounts: (self.accounts[a].clone(), self.accounts[b].clone()),
+                    events: (counts[a], counts[b]),
+                    co_occurrences: stats.co_occurrences,
+                    expected,
+                    lift: stats.co_occurrences as f64 / expected.max(f64::MIN_POSITIVE),
+                    p_value,
+                    example_blocks: stats.examples,
+                },
+            ));
+        }
+
+        CoordinationReport {
+            events_scanned: active.len(),
+            span_days: span as f64 / NANOS_PER_DAY as f64,
+            window_seconds: config.window_nanos / NANOS_PER_SECOND,
+            pairs_tested,
+            sets: group_pairs(significant, &self.accounts),
+        }
+    }
+}
+
+/// Join significant pairs that share an account into account sets
+fn group_pairs(pairs: Vec<(usize, usize, CoordinatedPair)>, accounts: &[String]) -> Vec<CoordinatedSet> {
+    let labels = label_map();
+    let mut set = DisjointSet::new(accounts.len());
+    for (a, b, _) in &pairs {
+        set.union(*a, *b);
+    }
+
+    let mut grouped: HashMap<usize, (BTreeSet<usize>, Vec<CoordinatedPair>)> = HashMap::new();
+    for (a, b, pair) in pairs {
+        let group = grouped.entry(set.find(a)).or_default();
+        group.0.extend([a, b]);
+        group.1.push(pair);
+    }
+
+    let mut sets: Vec<CoordinatedSet> = grouped
+        .into_values()
+        .map(|(members, mut pairs)| {
+            pairs.sort_by(|x, y| x.p_value.total_cmp(&y.p_value));
+            let accounts: Vec<String> = members.into_iter().map(|id| accounts[id].clone()).collect();
+            CoordinatedSet {
+                labels: accounts
+                    .iter()
+                    .filter_map(|a| labels.get(a).map(|(name, ty)| (a.clone(), format!("{} ({})", name, ty))))
+                    .collect(),
+                co_occurrences: pairs.iter().map(|p| p.co_occurrences).sum(),
+                min_p_value: pairs.first().map(|p| p.p_value).unwrap_or(1.0),
+                accounts,
+                pairs,
+            }
+        })
+        .collect();
+
+    sets.sort_by(|a, b| a.min_p_value.total_cmp(&b.min_p_value).then_with(|| b.co_occurrences.cmp(&a.co_occurrences)));
+    sets
+}
+
+/// Run coordinated-timing detection over ledger.db
+pub async fn run_coordination(db_path: &str, config: CoordinationConfig) -> Result<()> {
+    println!("===== COORDINATED TIMING =====");
+    println!("Database: {}", db_path);
+    println!(
+        "Window: {}s, min co-occurrences: {}, max corrected p-value: {}",
+        config.window_nanos / NANOS_PER_SECOND,
+        config.min_co_occurrences,
+        config.max_p_value
+    );
+
+    let db = LedgerDatabase::new(db_path)?;
+    let mut collector = TimingCollector::default();
+    db.for_each_transaction(None, |tx| {
+        collector.observe(&tx, &config);
+        Ok(())
+    })?;
+
+    let report = collector.finish(&config);
+    println!("Actions scanned: {} over {:.0} days", report.events_scanned, report.span_days);
+    println!("Account pairs tested: {}", report.pairs_tested);
+    println!("\nCoordinated account sets: {}", report.sets.len());
+    for set in report.sets.iter().take(20) {
+        println!(
+            "  {} accounts, {} co-occurrences, p = {:.2e}{}",
+            set.accounts.len(),
+            set.co_occurrences,
+            set.min_p_value,
+            set.labels.first().map(|(_, label)| format!(", includes {}", label)).unwrap_or_default()
+        );
+    }
+
+    let file_name = "./coordination_report.json";
+    std::fs::write(file_name, serde_json::to_string_pretty(&report)?)?;
+
+    println!("\nCoordination report saved to: {}", file_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger_db::test_transaction;
+
+    fn tx(id: u64, from: &str, seconds: u64) -> DbTransaction {
+        DbTransaction {
+            fee: Some(10_000),
+            timestamp: Some(seconds * NANOS_PER_SECOND),
+            ..test_transaction(id, "Transfer", Some(from), Some("sink"), 500_000_000)
+        }
+    }
+
+    #[test]
+    fn test_coordinated_accounts() {
+        let config = CoordinationConfig::default();
+        let mut collector = TimingCollector::default();
+        let mut block = 0;
+        let mut next = || {
+            block += 1;
+            block
+        };
+
+        for day in 0..30u64 {
+            let base = day * 86_400;
+            // bot1 and bot2 always act seconds apart, loner acts at unrelated times
+            collector.observe(&tx(next(), "bot1", base + 3_600), &config);
+            collector.observe(&tx(next(), "bot2", base + 3_605), &config);
+            collector.observe(&tx(next(), "loner", base + 40_000 + day * 97), &config);
+            collector.observe(&tx(next(), "other", base + 70_000), &config);
+        }
+        // A single chance meeting is not enough
+        collector.observe(&tx(next(), "loner", 86_400 * 31 + 100), &config);
+        collector.observe(&tx(next(), "other", 86_400 * 31 + 110), &config);
+
+        let report = collector.finish(&config);
+        assert_eq!(report.sets.len(), 1);
+        let set = &report.sets[0];
+        assert_eq!(set.accounts, vec!["bot1".to_string(), "bot2".to_string()]);
+        assert_eq!(set.pairs[0].co_occurrences, 30);
+        assert_eq!(set.pairs[0].example_blocks[0], (1, 2));
+        assert!(set.pairs[0].p_value < 1e-10);
+    }
+
+    #[test]
+    fn test_poisson_tail() {
+        assert!((poisson_tail(1, 1.0) - (1.0 - (-1.0f64).exp())).abs() < 1e-9);
+        assert_eq!(poisson_tail(0, 5.0), 1.0);
+        assert!((poisson_tail(3, 2.0) - (1.0 - 5.0 * (-2.0f64).exp())).abs() < 1e-9);
+        assert!(poisson_tail(30, 1.0) < 1e-30);
+
+        // Far below the mean, for account pairs that meet constantly by chance
+        assert!((poisson_tail(3, 12_000.0) - 1.0).abs() < 1e-9);
+        assert!((poisson_tail(3, 50_000.0) - 1.0).abs() < 1e-9);
+        assert!((poisson_tail(12_000, 12_000.0) - 0.5).abs() < 0.01);
+    }
+}
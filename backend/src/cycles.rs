@@ -0,0 +1,248 @@
+// Multi-hop cycle detection over ledger.db
+// Finds funds that leave an account and come back through a time-ordered chain of transfers
+// of roughly the same amount
+
+use crate::{
+    entities::label_map,
+    ledger_db::LedgerDatabase,
+    transfer_graph::{GraphFilter, TransferEdge, TransferGraph},
+    Type,
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+#[derive(Debug, Clone)]
+pub struct CycleConfig {
+    pub min_hops: usize,
+    pub max_hops: usize,
+    /// Relative difference allowed between each hop's amount and the first hop's
+    pub amount_tolerance: f64,
+    pub max_duration_nanos: u64,
+    /// Stop after this many cycles
+    pub max_results: usize,
+}
+
+impl Default for CycleConfig {
+    fn default() -> Self {
+        Self {
+            min_hops: 2,
+            max_hops: 6,
+            amount_tolerance: 0.1,
+            max_duration_nanos: 30 * 24 * 60 * 60 * NANOS_PER_SECOND,
+            max_results: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Cycle {
+    pub accounts: Vec<String>,
+    pub labels: Vec<Option<String>>,
+    pub transfers: Vec<TransferEdge>,
+    pub blocks: Vec<u64>,
+    pub start_block: u64,
+    pub end_block: u64,
+    pub duration_secs: u64,
+    pub amount_out_e8s: u64,
+    pub amount_back_e8s: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CycleReport {
+    pub transfers_scanned: usize,
+    pub max_hops: usize,
+    pub amount_tolerance: f64,
+    pub max_duration_secs: u64,
+    pub truncated: bool,
+    pub cycles: Vec<Cycle>,
+}
+
+struct Search<'a> {
+    graph: &'a TransferGraph,
+    config: &'a CycleConfig,
+    start: &'a TransferEdge,
+    path: Vec<usize>,
+    visited: Vec<&'a str>,
+    found: Vec<Vec<usize>>,
+}
+
+impl<'a> Search<'a> {
+    fn similar(&self, amount: u64) -> bool {
+        let reference = self.start.amount as f64;
+        (amount as f64 - reference).abs() <= reference * self.config.amount_tolerance
+    }
+
+    fn extend(&mut self, account: &'a str, after: &'a TransferEdge) {
+        if self.path.len() >= self.config.max_hops {
+            return;
+        }
+        let deadline = self.start.timestamp.saturating_add(self.config.max_duration_nanos);
+
+        // Outgoing lists are in time order, so skip straight to the first transfer after `after`
+        let indices = self.graph.outgoing_indices(account);
+        let first = indices.partition_point(|i| {
+            (self.graph.edges[*i].timestamp, self.graph.edges[*i].block) <= (after.timestamp, after.block)
+        });
+
+        for &i in &indices[first..] {
+            let edge = &self.graph.edges[i];
+            if edge.timestamp > deadline {
+                break;
+            }
+            if !self.similar(edge.amount) {
+                continue;
+            }
+
+            if edge.to == self.start.from {
+                if self.path.len() + 1 >= self.config.min_hops {
+                    let mut cycle = self.path.clone();
+                    cycle.push(i);
+                    self.found.push(cycle);
+                }
+                continue;
+            }
+            if self.visited.contains(&edge.to.as_str()) {
+                continue;
+            }
+
+            self.path.push(i);
+            self.visited.push(edge.to.as_str());
+            self.extend(edge.to.as_str(), edge);
+            self.visited.pop();
+            self.path.pop();
+        }
+    }
+}
+
+/// Every time-ordered cycle that starts with a transfer in the graph
+pub fn find_cycles(graph: &TransferGraph, config: &CycleConfig) -> (Vec<Vec<usize>>, bool) {
+    let mut cycles = Vec::new();
+    let mut seen: HashSet<Vec<u64>> = HashSet::new();
+
+    for (i, start) in graph.edges.iter().enumerate() {
+        let mut search = Search {
+            graph,
+            config,
+            start,
+            path: vec![i],
+            visited: vec![start.from.as_str(), start.to.as_str()],
+            found: Vec::new(),
+        };
+        search.extend(start.to.as_str(), start);
+
+        for cycle in search.found {
+            let mut key: Vec<u64> = cycle.iter().map(|e| graph.edges[*e].block).collect();
+            key.sort_unstable();
+            if seen.insert(key) {
+                cycles.push(cycle);
+                if cycles.len() >= config.max_results {
+                    return (cycles, true);
+                }
+            }
+        }
+    }
+
+    (cycles, false)
+}
+
+fn describe(graph: &TransferGraph, cycle: &[usize], labels: &HashMap<String, (String, Type)>) -> Cycle {
+    let transfers: Vec<TransferEdge> = cycle.iter().map(|i| graph.edges[*i].clone()).collect();
+    let first = &transfers[0];
+    let last = &transfers[transfers.len() - 1];
+    let accounts: Vec<String> = transfers.iter().map(|t| t.from.clone()).collect();
+
+    Cycle {
+        labels: accounts.iter().map(|a| labels.get(a).map(|(name, ty)| format!("{} ({})", name, ty))).collect(),
+        accounts,
+        blocks: transfers.iter().map(|t| t.block).collect(),
+        start_block: first.block,
+        end_block: last.block,
+        duration_secs: (last.timestamp - first.timestamp) / NANOS_PER_SECOND,
+        amount_out_e8s: first.amount,
+        amount_back_e8s: last.amount,
+        transfers,
+    }
+}
+
+/// Run the cycle detection over ledger.db
+pub async fn run_cycles(db_path: &str, filter: GraphFilter, config: CycleConfig) -> Result<()> {
+    println!("===== CYCLE DETECTION =====");
+    println!("Database: {}", db_path);
+    println!(
+        "Hops: {}-{}, amount tolerance: {:.0}%, max duration: {} days",
+        config.min_hops,
+        config.max_hops,
+        config.amount_tolerance * 100.0,
+        config.max_duration_nanos / (24 * 60 * 60 * NANOS_PER_SECOND)
+    );
+
+    let db = LedgerDatabase::new(db_path)?;
+    let graph = TransferGraph::load(&db, &filter)?;
+    println!("Loaded {} transfers", graph.edges.len());
+
+    let (found, truncated) = find_cycles(&graph, &config);
+    let labels = label_map();
+    let mut cycles: Vec<Cycle> = found.iter().map(|c| describe(&graph, c, &labels)).collect();
+    cycles.sort_by_key(|c| std::cmp::Reverse(c.amount_out_e8s));
+
+    println!("\nCycles found: {}{}", cycles.len(), if truncated { " (truncated)" } else { "" });
+    for cycle in cycles.iter().take(20) {
+        println!(
+            "  {} hops, {} ICP, blocks {}..{}, {} hours",
+            cycle.transfers.len(),
+            cycle.amount_out_e8s as f64 / 100_000_000.0,
+            cycle.start_block,
+            cycle.end_block,
+            cycle.duration_secs / 3600
+        );
+    }
+
+    let report = CycleReport {
+        transfers_scanned: graph.edges.len(),
+        max_hops: config.max_hops,
+        amount_tolerance: config.amount_tolerance,
+        max_duration_secs: config.max_duration_nanos / NANOS_PER_SECOND,
+        truncated,
+        cycles,
+    };
+
+    let file_name = "./cycles_report.json";
+    std::fs::write(file_name, serde_json::to_string_pretty(&report)?)?;
+
+    println!("\nCycles saved to: {}", file_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(block: u64, from: &str, to: &str, amount: u64) -> TransferEdge {
+        TransferEdge { block, from: from.to_string(), to: to.to_string(), amount, timestamp: block * NANOS_PER_SECOND }
+    }
+
+    #[test]
+    fn test_time_ordered_cycles() {
+        let graph = TransferGraph::from_edges(vec![
+            edge(1, "b", "c", 1000), // before a -> b, so not part of the loop
+            edge(2, "a", "b", 1000),
+            edge(3, "b", "c", 980),
+            edge(4, "c", "a", 960),
+            edge(5, "b", "d", 500), // amount too different
+            edge(6, "d", "a", 500),
+        ]);
+
+        let (cycles, truncated) = find_cycles(&graph, &CycleConfig::default());
+        assert!(!truncated);
+        let blocks: Vec<Vec<u64>> = cycles.iter().map(|c| c.iter().map(|i| graph.edges[*i].block).collect()).collect();
+        assert_eq!(blocks, vec![vec![2, 3, 4]]);
+
+        let cycle = describe(&graph, &cycles[0], &HashMap::new());
+        assert_eq!(cycle.accounts, vec!["a", "b", "c"]);
+        assert_eq!(cycle.duration_secs, 2);
+    }
+}
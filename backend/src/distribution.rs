@@ -0,0 +1,230 @@
+// Rich list and balance distribution over the whole ledger
+// Concentration metrics are computed from a replayed LedgerState
+
+use crate::{
+    entities::label_map,
+    ledger_db::LedgerDatabase,
+    replay::{replay_to, LedgerState, PointInTime},
+};
+use serde::Serialize;
+
+// Buckets reported by the distribution snapshot, largest first (e8s, exclusive lower bound)
+pub const BALANCE_BUCKETS: &[(&str, u64)] = &[
+    ("> 1M ICP", 100_000_000_000_000),
+    ("> 100K ICP", 10_000_000_000_000),
+    ("> 10K ICP", 1_000_000_000_000),
+    ("> 1K ICP", 100_000_000_000),
+];
+
+#[derive(Debug, Serialize)]
+pub struct Holder {
+    pub rank: usize,
+    pub account: String,
+    pub label: Option<String>,
+    pub balance_e8s: u64,
+    pub share_of_supply: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BalanceBucket {
+    pub label: String,
+    pub min_balance_e8s: u64,
+    pub accounts: usize,
+    pub total_balance_e8s: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConcentrationMetrics {
+    pub holders: usize,
+    pub gini: f64,
+    /// Herfindahl-Hirschman index on the 0-10,000 scale
+    pub hhi: f64,
+    pub top_10_share: f64,
+    pub top_100_share: f64,
+    pub top_1000_share: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RichListReport {
+    pub block: Option<u64>,
+    pub timestamp: Option<u64>,
+    pub total_supply_e8s: i64,
+    pub total_held_e8s: u64,
+    pub top_holders: Vec<Holder>,
+    pub concentration: ConcentrationMetrics,
+    pub buckets: Vec<BalanceBucket>,
+}
+
+/// Gini coefficient of a set of balances (0 = equal, 1 = one holder owns everything)
+pub fn gini(balances: &[u64]) -> f64 {
+    let mut sorted = balances.to_vec();
+    sorted.sort_unstable();
+
+    let n = sorted.len() as f64;
+    let total: f64 = sorted.iter().map(|b| *b as f64).sum();
+    if sorted.is_empty() || total == 0.0 {
+        return 0.0;
+    }
+
+    let weighted: f64 = sorted.iter().enumerate().map(|(i, b)| (i + 1) as f64 * *b as f64).sum();
+    (2.0 * weighted) / (n * total) - (n + 1.0) / n
+}
+
+/// Herfindahl-Hirschman index: sum of squared percentage shares
+pub fn hhi(balances: &[u64]) -> f64 {
+    let total: f64 = balances.iter().map(|b| *b as f64).sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+
+    balances.iter().map(|b| (*b as f64 / total * 100.0).powi(2)).sum()
+}
+
+/// Share of the total held by the `k` largest balances; expects descending order
+pub fn top_k_share(sorted_desc: &[u64], k: usize) -> f64 {
+    let total: f64 = sorted_desc.iter().map(|b| *b as f64).sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+
+    sorted_desc.iter().take(k).map(|b| *b as f64).sum::<f64>() / total
+}
+
+/// Count accounts and balances above each of BALANCE_BUCKETS
+pub fn balance_buckets(balances: &[u64]) -> Vec<BalanceBucket> {
+    BALANCE_BUCKETS
+        .iter()
+        .map(|(label, min)| {
+            let above: Vec<u64> = balances.iter().copied().filter(|b| b > min).collect();
+            BalanceBucket {
+                label: label.to_string(),
+                min_balance_e8s: *min,
+                accounts: above.len(),
+                total_balance_e8s: above.iter().sum(),
+            }
+        })
+        .collect()
+}
+
+/// Build the rich list and concentration snapshot from a replayed state
+pub fn build_rich_list(state: &LedgerState, top_n: usize) -> RichListReport {
+    let labels = label_map();
+
+    let mut holders: Vec<(&String, u64)> = state.positive_balances().collect();
+    holders.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    let balances: Vec<u64> = holders.iter().map(|(_, balance)| *balance).collect();
+    let total_held: u64 = balances.iter().sum();
+    let supply = state.total_supply().max(1) as f64;
+
+    let top_holders = holders
+        .iter()
+        .take(top_n)
+        .enumerate()
+        .map(|(i, (account, balance))| Holder {
+            rank: i + 1,
+            account: account.to_string(),
+            label: labels.get(*account).map(|(name, ty)| format!("{} ({})", name, ty)),
+            balance_e8s: *balance,
+            share_of_supply: *balance as f64 / supply,
+        })
+        .collect();
+
+    RichListReport {
+        block: state.last_block,
+        timestamp: state.last_timestamp,
+        total_supply_e8s: state.total_supply(),
+        total_held_e8s: total_held,
+        top_holders,
+        concentration: ConcentrationMetrics {
+            holders: balances.len(),
+            gini: gini(&balances),
+            hhi: hhi(&balances),
+            top_10_share: top_k_share(&balances, 10),
+            top_100_share: top_k_share(&balances, 100),
+            top_1000_share: top_k_share(&balances, 1000),
+        },
+        buckets: balance_buckets(&balances),
+    }
+}
+
+/// Run the rich list snapshot against ledger.db
+pub async fn run_rich_list(db_path: &str, at: Option<&str>, top_n: usize) -> anyhow::Result<()> {
+    println!("===== RICH LIST =====");
+    println!("Database: {}", db_path);
+
+    let db = LedgerDatabase::new(db_path)?;
+    let last_block = match at {
+        Some(at) => {
+            let point = PointInTime::parse(at)?;
+            let block = point.resolve_block(&db)?;
+            if block.is_none() {
+                anyhow::bail!("no transactions at or before {}", at);
+            }
+            block
+        }
+        None => db.get_last_block()?,
+    };
+
+    println!("Replaying ledger up to block {:?}...", last_block);
+    let state = replay_to(&db, last_block)?;
+    let report = build_rich_list(&state, top_n);
+
+    println!("\nTotal supply: {} ICP", report.total_supply_e8s as f64 / 100_000_000.0);
+    println!("Holders: {}", report.concentration.holders);
+    println!("Gini: {:.4}", report.concentration.gini);
+    println!("HHI: {:.2}", report.concentration.hhi);
+    println!("Top 10 share: {:.2}%", report.concentration.top_10_share * 100.0);
+    println!("Top 100 share: {:.2}%", report.concentration.top_100_share * 100.0);
+    println!("Top 1000 share: {:.2}%", report.concentration.top_1000_share * 100.0);
+
+    println!("\nTop {} Holders:", top_n);
+    for holder in &report.top_holders {
+        println!(
+            "{}. {} {} - {} ICP ({:.3}%)",
+            holder.rank,
+            &holder.account[..8],
+            holder.label.as_deref().unwrap_or(""),
+            holder.balance_e8s as f64 / 100_000_000.0,
+            holder.share_of_supply * 100.0
+        );
+    }
+
+    println!("\nBalance Distribution:");
+    for bucket in &report.buckets {
+        println!("  {}: {} accounts", bucket.label, bucket.accounts);
+    }
+
+    let file_name = format!("./rich_list_{}.json", at.unwrap_or("latest"));
+    std::fs::write(&file_name, serde_json::to_string_pretty(&report)?)?;
+
+    println!("\nRich list saved to: {}", file_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concentration_metrics() {
+        assert_eq!(gini(&[5, 5, 5, 5]), 0.0);
+        assert!((gini(&[0, 0, 0, 100]) - 0.75).abs() < 1e-9);
+
+        assert!((hhi(&[100]) - 10_000.0).abs() < 1e-9);
+        assert!((hhi(&[50, 50]) - 5_000.0).abs() < 1e-9);
+
+        assert!((top_k_share(&[60, 30, 10], 1) - 0.6).abs() < 1e-9);
+        assert!((top_k_share(&[60, 30, 10], 10) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_balance_buckets() {
+        let icp = 100_000_000;
+        let buckets = balance_buckets(&[2_000_000 * icp, 50_000 * icp, 1_000 * icp]);
+
+        let counts: Vec<usize> = buckets.iter().map(|b| b.accounts).collect();
+        assert_eq!(counts, vec![1, 1, 2, 2]);
+    }
+}
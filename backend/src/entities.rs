@@ -0,0 +1,392 @@
+// Entity-level aggregation over the labelled address groups in addresses.rs
+// An entity is a name (e.g. "Coinbase") or a whole category (e.g. "cex") resolved to all of its accounts
+
+use crate::{
+    addresses::{CEXES, DEFI, FOUNDATION, IDENTIFIED, NODE_PROVIDERS, SNSES, SPAMMERS, SUSPECTS},
+    helper::principal_to_account_id,
+    ledger_db::{DbTransaction, LedgerDatabase, NANOS_PER_DAY},
+    pattern_addresses::get_pattern_address_list,
+    AccountData, Type,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entity {
+    pub name: String,
+    pub ty: Type,
+    pub accounts: Vec<String>,
+}
+
+impl From<&AccountData> for Entity {
+    fn from(data: &AccountData) -> Self {
+        // Principals are resolved to their default subaccount
+        let mut accounts: Vec<String> = data
+            .principals
+            .iter()
+            .map(|p| hex::encode(principal_to_account_id(p, None)))
+            .chain(data.accounts.iter().cloned())
+            .collect();
+        accounts.sort();
+        accounts.dedup();
+
+        Self { name: data.name.clone(), ty: data.ty.clone(), accounts }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct EntityStats {
+    pub transaction_count: usize,
+    pub internal_transfer_count: usize,
+    pub total_received_e8s: u64,
+    pub total_sent_e8s: u64,
+    pub fees_paid_e8s: u64,
+    pub balance_e8s: i64,
+    pub first_transaction_timestamp: Option<u64>,
+    pub last_transaction_timestamp: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Counterparty {
+    pub account: String,
+    pub label: Option<String>,
+    pub received_from_e8s: u64,
+    pub sent_to_e8s: u64,
+    pub transaction_count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EntityReport {
+    pub name: String,
+    pub accounts: Vec<String>,
+    pub stats: EntityStats,
+    pub daily_balances: Vec<(u64, i64)>,
+    pub counterparties: Vec<Counterparty>,
+}
+
+// all_entries
+// every labelled group from addresses.rs, in the order the graph data uses
+pub fn all_entries() -> Vec<AccountData> {
+    let mut entries = Vec::new();
+
+    // single
+    entries.extend(DEFI.iter().map(|(name, addr)| AccountData::new(name, &[addr], Type::Defi)));
+    entries.extend(SNSES.iter().map(|(name, addr)| AccountData::new(name, &[addr], Type::Sns)));
+
+    // unnamed
+    entries.extend(SPAMMERS.iter().map(|addr| AccountData::new(&addr[..5], &[addr], Type::Spammer)));
+
+    // multiple
+    entries.extend(CEXES.iter().map(|(name, addrs)| AccountData::new(name, addrs, Type::Cex)));
+    entries.extend(FOUNDATION.iter().map(|(name, addrs)| AccountData::new(name, addrs, Type::Foundation)));
+    entries.extend(IDENTIFIED.iter().map(|(name, addrs)| AccountData::new(name, addrs, Type::Identified)));
+    entries.extend(NODE_PROVIDERS.iter().map(|(name, addrs)| AccountData::new(name, addrs, Type::NodeProvider)));
+    entries.extend(SUSPECTS.iter().map(|(name, addrs)| AccountData::new(name, addrs, Type::Suspect)));
+
+    entries
+}
+
+pub fn all_entities() -> Vec<Entity> {
+    all_entries().iter().map(Entity::from).collect()
+}
+
+/// Map every labelled account to its entity name and category
+pub fn label_map() -> HashMap<String, (String, Type)> {
+    let mut labels = HashMap::new();
+
+    for entity in all_entities() {
+        for account in entity.accounts {
+            labels.entry(account).or_insert_with(|| (entity.name.clone(), entity.ty.clone()));
+        }
+    }
+
+    labels
+}
+
+/// Parse a category name such as "cex", "node_provider" or "NodeProvider"
+pub fn parse_category(query: &str) -> Option<Type> {
+    let normalized: String = query.chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_lowercase();
+
+    [
+        Type::Cex,
+        Type::Defi,
+        Type::Foundation,
+        Type::Identified,
+        Type::NodeProvider,
+        Type::Spammer,
+        Type::Sns,
+        Type::Suspect,
+    ]
+    .into_iter()
+    .find(|ty| ty.to_string().to_lowercase() == normalized)
+}
+
+/// Resolve an entity name or category to a single combined entity
+///
+/// Names are matched case-insensitively; a category combines every entity of that type.
+pub fn resolve_entity(query: &str) -> Option<Entity> {
+    let entities = all_entities();
+
+    let (name, ty, matched): (String, Type, Vec<&Entity>) = if let Some(ty) = parse_category(query) {
+        (ty.to_string(), ty.clone(), entities.iter().filter(|e| e.ty == ty).collect())
+    } else {
+        let matched: Vec<&Entity> = entities.iter().filter(|e| e.name.eq_ignore_ascii_case(query)).collect();
+        let first = matched.first()?;
+        (first.name.clone(), first.ty.clone(), matched)
+    };
+
+    let mut accounts: Vec<String> = matched.iter().flat_map(|e| e.accounts.iter().cloned()).collect();
+    accounts.sort();
+    accounts.dedup();
+
+    if accounts.is_empty() {
+        return None;
+    }
+
+    Some(Entity { name, ty, accounts })
+}
+
+/// Resolve a set of accounts from the command line
+///
+/// Accepts "seeds" for the pattern seed addresses, an entity name or category,
+/// a file with one account per line, or a single account hex.
+pub fn resolve_account_set(spec: &str) -> anyhow::Result<Vec<String>> {
+    if spec.eq_ignore_ascii_case("seeds") {
+        return Ok(get_pattern_address_list());
+    }
+    // Plural category names ("suspects") are accepted too
+    if let Some(entity) = resolve_entity(spec).or_else(|| spec.strip_suffix('s').and_then(resolve_entity)) {
+        return Ok(entity.accounts);
+    }
+    if std::path::Path::new(spec).is_file() {
+        let contents = std::fs::read_to_string(spec)?;
+        return Ok(contents
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(String::from)
+            .collect());
+    }
+    if spec.len() == 64 && spec.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(vec![spec.to_lowercase()]);
+    }
+
+    anyhow::bail!("'{}' is not 'seeds', an entity, a category, a file or an account", spec)
+}
+
+/// Combine the transactions of all an entity's accounts into one report
+///
+/// Transfers between the entity's own accounts are excluded from the flows and
+/// counterparties; only their fee is deducted from the balance.
+pub fn build_entity_report(
+    entity: &Entity,
+    transactions: &[DbTransaction],
+    labels: &HashMap<String, (String, Type)>,
+) -> EntityReport {
+    let members: HashSet<&str> = entity.accounts.iter().map(|a| a.as_str()).collect();
+    let is_member = |account: &str| members.contains(account);
+
+    let mut stats = EntityStats {
+        transaction_count: 0,
+        internal_transfer_count: 0,
+        total_received_e8s: 0,
+        total_sent_e8s: 0,
+        fees_paid_e8s: 0,
+        balance_e8s: 0,
+        first_transaction_timestamp: None,
+        last_transaction_timestamp: None,
+    };
+    let mut counterparties: HashMap<String, Counterparty> = HashMap::new();
+    let mut daily_balances: Vec<(u64, i64)> = Vec::new();
+
+    for tx in transactions {
+        let from_member = tx.from_account.as_deref().is_some_and(is_member);
+        let to_member = tx.to_account.as_deref().is_some_and(is_member);
+        let amount = tx.amount.unwrap_or(0);
+
+        stats.transaction_count += 1;
+        stats.balance_e8s += tx.balance_delta(is_member);
+
+        if let Some(timestamp) = tx.timestamp {
+            stats.first_transaction_timestamp =
+                Some(stats.first_transaction_timestamp.map_or(timestamp, |t| t.min(timestamp)));
+            stats.last_transaction_timestamp =
+                Some(stats.last_transaction_timestamp.map_or(timestamp, |t| t.max(timestamp)));
+        }
+
+        if from_member && matches!(tx.operation_type.as_str(), "Transfer" | "Approve") {
+            stats.fees_paid_e8s += tx.fee.unwrap_or(0);
+        }
+
+        if tx.operation_type == "Transfer" {
+            if from_member && to_member {
+                stats.internal_transfer_count += 1;
+            } else if to_member {
+                stats.total_received_e8s += amount;
+                if let Some(from) = &tx.from_account {
+                    let entry = counterparty_entry(&mut counterparties, from, labels);
+                    entry.received_from_e8s += amount;
+                    entry.transaction_count += 1;
+                }
+            } else if from_member {
+                stats.total_sent_e8s += amount;
+                if let Some(to) = &tx.to_account {
+                    let entry = counterparty_entry(&mut counterparties, to, labels);
+                    entry.sent_to_e8s += amount;
+                    entry.transaction_count += 1;
+                }
+            }
+        } else if tx.operation_type == "Mint" && to_member {
+            stats.total_received_e8s += amount;
+        } else if tx.operation_type == "Burn" && from_member {
+            stats.total_sent_e8s += amount;
+        }
+
+        // Track the closing balance of each day
+        if let Some(timestamp) = tx.timestamp {
+            let day = timestamp / NANOS_PER_DAY;
+            match daily_balances.last_mut() {
+                Some((last_day, balance)) if *last_day == day => *balance = stats.balance_e8s,
+                _ => daily_balances.push((day, stats.balance_e8s)),
+            }
+        }
+    }
+
+    let mut counterparties: Vec<Counterparty> = counterparties.into_values().collect();
+    counterparties.sort_by_key(|c| std::cmp::Reverse(c.received_from_e8s + c.sent_to_e8s));
+
+    EntityReport {
+        name: entity.name.clone(),
+        accounts: entity.accounts.clone(),
+        stats,
+        daily_balances: fill_daily_gaps(&daily_balances),
+        counterparties,
+    }
+}
+
+fn counterparty_entry<'a>(
+    counterparties: &'a mut HashMap<String, Counterparty>,
+    account: &str,
+    labels: &HashMap<String, (String, Type)>,
+) -> &'a mut Counterparty {
+    counterparties.entry(account.to_string()).or_insert_with(|| Counterparty {
+        account: account.to_string(),
+        label: labels.get(account).map(|(name, ty)| format!("{} ({})", name, ty)),
+        received_from_e8s: 0,
+        sent_to_e8s: 0,
+        transaction_count: 0,
+    })
+}
+
+// Carry balances forward over days without activity
+fn fill_daily_gaps(balances: &[(u64, i64)]) -> Vec<(u64, i64)> {
+    let mut filled = Vec::new();
+
+    for window in balances.windows(2) {
+        let (day, balance) = window[0];
+        let (next_day, _) = window[1];
+        filled.extend((day..next_day).map(|d| (d, balance)));
+    }
+    filled.extend(balances.last().copied());
+
+    filled
+}
+
+/// Run the entity report for a name or category against ledger.db
+pub async fn run_entity_report(query: &str, db_path: &str) -> anyhow::Result<()> {
+    println!("===== ENTITY REPORT =====");
+    println!("Entity: {}", query);
+    println!("Database: {}", db_path);
+
+    let Some(entity) = resolve_entity(query) else {
+        anyhow::bail!("no entity or category named '{}'", query);
+    };
+
+    println!("Resolved {} ({}) to {} accounts", entity.name, entity.ty, entity.accounts.len());
+
+    let db = LedgerDatabase::new(db_path)?;
+    let transactions = db.get_transactions_for_accounts(&entity.accounts)?;
+    let report = build_entity_report(&entity, &transactions, &label_map());
+
+    println!("\nEntity Statistics:");
+    println!(
+        "  Transactions: {} ({} internal transfers excluded)",
+        report.stats.transaction_count, report.stats.internal_transfer_count
+    );
+    println!("  Total received: {} ICP", report.stats.total_received_e8s as f64 / 100_000_000.0);
+    println!("  Total sent: {} ICP", report.stats.total_sent_e8s as f64 / 100_000_000.0);
+    println!("  Fees paid: {} ICP", report.stats.fees_paid_e8s as f64 / 100_000_000.0);
+    println!("  Balance: {} ICP", report.stats.balance_e8s as f64 / 100_000_000.0);
+
+    println!("\nTop Counterparties:");
+    for (i, counterparty) in report.counterparties.iter().take(20).enumerate() {
+        println!(
+            "{}. {} {} - Received: {} ICP, Sent: {} ICP",
+            i + 1,
+            &counterparty.account[..8],
+            counterparty.label.as_deref().unwrap_or(""),
+            counterparty.received_from_e8s as f64 / 100_000_000.0,
+            counterparty.sent_to_e8s as f64 / 100_000_000.0
+        );
+    }
+
+    let slug: String =
+        entity.name.chars().map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '_' }).collect();
+    let file_name = format!("./entity_{}_report.json", slug);
+    std::fs::write(&file_name, serde_json::to_string_pretty(&report)?)?;
+
+    println!("\nEntity report saved to: {}", file_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger_db::test_transaction;
+
+    fn transfer(id: u64, from: &str, to: &str, amount: u64, day: u64) -> DbTransaction {
+        DbTransaction {
+            fee: Some(10_000),
+            timestamp: Some(day * NANOS_PER_DAY),
+            ..test_transaction(id, "Transfer", Some(from), Some(to), amount)
+        }
+    }
+
+    #[test]
+    fn test_resolve_name_and_category() {
+        let coinbase = resolve_entity("coinbase").unwrap();
+        assert_eq!(coinbase.accounts.len(), 6);
+
+        let cexes = resolve_entity("cex").unwrap();
+        assert_eq!(cexes.ty, Type::Cex);
+        assert!(cexes.accounts.len() > coinbase.accounts.len());
+
+        // Principals resolve to their default account
+        let fund = resolve_entity("Foundation Neuron Fund").unwrap();
+        assert_eq!(fund.accounts.len(), 1);
+        assert_eq!(fund.accounts[0].len(), 64);
+
+        assert!(resolve_entity("no such entity").is_none());
+    }
+
+    #[test]
+    fn test_internal_transfers_excluded() {
+        let entity = Entity { name: "Test".to_string(), ty: Type::Suspect, accounts: vec!["a".into(), "b".into()] };
+        let transactions = vec![
+            transfer(1, "x", "a", 1_000_000, 0),
+            transfer(2, "a", "b", 500_000, 2),
+            transfer(3, "b", "y", 200_000, 3),
+        ];
+
+        let report = build_entity_report(&entity, &transactions, &HashMap::new());
+
+        assert_eq!(report.stats.internal_transfer_count, 1);
+        assert_eq!(report.stats.total_received_e8s, 1_000_000);
+        assert_eq!(report.stats.total_sent_e8s, 200_000);
+        assert_eq!(report.stats.balance_e8s, 1_000_000 - 200_000 - 2 * 10_000);
+        assert_eq!(report.counterparties.len(), 2);
+        assert_eq!(report.daily_balances.len(), 4);
+        assert_eq!(report.daily_balances[1], (1, 1_000_000));
+    }
+}
@@ -0,0 +1,354 @@
+// Offline pattern sweeps over ledger.db and the findings review workflow
+// Runs every enabled detector over each account in parallel, stores the hits in the findings table
+// under a stable id, and lets reviewers list, triage and export them
+
+use crate::{
+    ledger_db::{DbFinding, DbTransaction, FindingFilter, LedgerDatabase},
+    pattern_detector::{PatternDetector, SuspiciousPattern, Transaction},
+};
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    time::Instant,
+};
+
+/// Review state of a finding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FindingStatus {
+    New,
+    Confirmed,
+    FalsePositive,
+    Dismissed,
+}
+
+impl FindingStatus {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().replace('-', "_").as_str() {
+            "new" => Ok(Self::New),
+            "confirmed" => Ok(Self::Confirmed),
+            "false_positive" | "fp" => Ok(Self::FalsePositive),
+            "dismissed" => Ok(Self::Dismissed),
+            other => bail!("unknown status '{}', expected new, confirmed, false_positive or dismissed", other),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::New => "new",
+            Self::Confirmed => "confirmed",
+            Self::FalsePositive => "false_positive",
+            Self::Dismissed => "dismissed",
+        }
+    }
+
+    /// Whether a finding in this state still counts against the account
+    pub fn is_active(status: &str) -> bool {
+        !matches!(Self::parse(status), Ok(Self::FalsePositive | Self::Dismissed))
+    }
+}
+
+/// Convert ledger rows into the detector's transaction format
+///
+/// Mints come from, and burns go to, the empty account; approvals move no funds and are skipped.
+pub fn pattern_transactions(transactions: &[DbTransaction]) -> Vec<Transaction> {
+    transactions
+        .iter()
+        .filter(|tx| matches!(tx.operation_type.as_str(), "Transfer" | "Mint" | "Burn"))
+        .map(|tx| Transaction {
+            from: tx.from_account.clone().unwrap_or_default(),
+            to: tx.to_account.clone().unwrap_or_default(),
+            amount: tx.amount.unwrap_or(0),
+            timestamp: tx.timestamp.unwrap_or(0),
+            block: tx.id,
+            fee: tx.fee.unwrap_or(0),
+        })
+        .collect()
+}
+
+/// Blocks behind a pattern's evidence
+///
+/// Withdrawal and deposit lists are context rather than evidence; matched holding periods are used instead.
+pub fn evidence_blocks(pattern: &SuspiciousPattern) -> Vec<u64> {
+    let mut blocks: Vec<u64> = pattern
+        .transfers
+        .iter()
+        .map(|t| t.block)
+        .chain(pattern.holding_periods.iter().flat_map(|p| [p.start_block, p.end_block]))
+        .chain(pattern.peels.iter().map(|p| p.block))
+        .collect();
+    blocks.sort_unstable();
+    blocks.dedup();
+    blocks
+}
+
+/// Stable id of a finding
+///
+/// Keyed on the first evidence block rather than all of them, so a pattern that grows as the
+/// account keeps trading (another holding period, say) is still recognised as the same finding.
+pub fn finding_id(account: &str, detector: &str, pattern: &SuspiciousPattern, blocks: &[u64]) -> Result<String> {
+    let anchor = match blocks.first() {
+        Some(block) => block.to_string(),
+        None => serde_json::to_string(pattern)?,
+    };
+
+    let mut hasher = Sha256::new();
+    for part in [account, detector, &pattern.pattern_type.name(), &anchor] {
+        hasher.update(part.as_bytes());
+        hasher.update(b"|");
+    }
+    Ok(hex::encode(&hasher.finalize()[..8]))
+}
+
+/// Run the enabled detectors on one account's history
+pub fn analyze_account(
+    detector: &PatternDetector,
+    account: &str,
+    transactions: &[DbTransaction],
+) -> Result<Vec<DbFinding>> {
+    let now = chrono::Utc::now().to_rfc3339();
+
+    detector
+        .detect_by_detector(account, &pattern_transactions(transactions))
+        .into_iter()
+        .map(|(detector, pattern)| {
+            let blocks = evidence_blocks(&pattern);
+            Ok(DbFinding {
+                id: 0,
+                finding_id: finding_id(account, detector.name(), &pattern, &blocks)?,
+                account: account.to_string(),
+                detector: detector.name().to_string(),
+                detector_version: detector.version().to_string(),
+                pattern_type: pattern.pattern_type.name(),
+                total_amount: pattern.total_amount,
+                evidence_blocks: blocks,
+                pattern: serde_json::to_string(&pattern)?,
+                status: FindingStatus::New.as_str().to_string(),
+                notes: None,
+                first_seen: now.clone(),
+                last_seen: now.clone(),
+                reviewed_at: None,
+            })
+        })
+        .collect()
+}
+
+/// Sweep `accounts` (every account when None) with `threads` workers, each reading through
+/// its own connection; results are merged into the findings table as they arrive. This blocks
+/// until the sweep finishes, so it is a plain function rather than an async one
+pub fn run_pattern_sweep(
+    db_path: &str,
+    detector: PatternDetector,
+    accounts: Option<Vec<String>>,
+    threads: usize,
+) -> Result<()> {
+    println!("===== PATTERN SWEEP =====");
+    println!("Database: {}", db_path);
+    println!("Detectors: {}", detector.enabled().join(", "));
+
+    let mut db = LedgerDatabase::new(db_path)?;
+    let accounts = match accounts {
+        Some(accounts) => accounts,
+        None => db.get_all_accounts()?,
+    };
+    let threads = threads.max(1);
+    println!("Accounts: {}, threads: {}", accounts.len(), threads);
+
+    let start_time = Instant::now();
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel::<Result<Vec<DbFinding>>>();
+
+    let mut inserted = 0;
+    let mut updated = 0;
+
+    std::thread::scope(|scope| -> Result<()> {
+        for _ in 0..threads {
+            let sender = sender.clone();
+            let (detector, accounts, next) = (&detector, &accounts, &next);
+            scope.spawn(move || {
+                let worker = || -> Result<()> {
+                    let db = LedgerDatabase::new(db_path)?;
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(account) = accounts.get(i) else { return Ok(()) };
+                        let transactions = db.get_account_transactions(account)?;
+                        let findings = analyze_account(detector, account, &transactions)?;
+                        if sender.send(Ok(findings)).is_err() {
+                            return Ok(());
+                        }
+                    }
+                };
+                if let Err(e) = worker() {
+                    let _ = sender.send(Err(e));
+                }
+            });
+        }
+        drop(sender);
+
+        for (done, result) in receiver.iter().enumerate() {
+            let (new, seen) = db.upsert_findings(&result?)?;
+            inserted += new;
+            updated += seen;
+
+            if (done + 1) % 10_000 == 0 {
+                println!(
+                    "  {} / {} accounts, {} new findings ({:.0}s)",
+                    done + 1,
+                    accounts.len(),
+                    inserted,
+                    start_time.elapsed().as_secs_f64()
+                );
+            }
+        }
+        Ok(())
+    })?;
+
+    println!(
+        "\nSweep complete in {:.1}s: {} new findings, {} already known",
+        start_time.elapsed().as_secs_f64(),
+        inserted,
+        updated
+    );
+    println!("Findings saved to the findings table in: {}", db_path);
+
+    Ok(())
+}
+
+/// Print the findings matching `filter`
+pub async fn run_list_findings(db_path: &str, filter: FindingFilter) -> Result<()> {
+    let db = LedgerDatabase::new(db_path)?;
+    let findings = db.get_findings(&filter)?;
+
+    println!("===== FINDINGS ({}) =====", findings.len());
+    for finding in &findings {
+        println!(
+            "{}  {:<15} {:<14} {:>14.2} ICP  {}  blocks: {}",
+            finding.finding_id,
+            finding.status,
+            finding.detector,
+            finding.total_amount as f64 / 100_000_000.0,
+            finding.account,
+            finding.evidence_blocks.iter().take(5).map(|b| b.to_string()).collect::<Vec<_>>().join(", ")
+        );
+        if let Some(notes) = &finding.notes {
+            println!("    notes: {}", notes);
+        }
+    }
+
+    Ok(())
+}
+
+/// Set a finding's review status, optionally with reviewer notes
+pub async fn run_update_finding(
+    db_path: &str,
+    finding_id: &str,
+    status: FindingStatus,
+    notes: Option<&str>,
+) -> Result<()> {
+    let db = LedgerDatabase::new(db_path)?;
+    if !db.update_finding(finding_id, status.as_str(), notes, &chrono::Utc::now().to_rfc3339())? {
+        bail!("no finding with id '{}'", finding_id);
+    }
+
+    println!("Finding {} marked {}", finding_id, status.as_str());
+    Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Export the findings matching `filter` as JSON, or CSV when the path ends in .csv
+pub async fn run_export_findings(db_path: &str, filter: FindingFilter, file_name: &str) -> Result<()> {
+    let db = LedgerDatabase::new(db_path)?;
+    let findings = db.get_findings(&filter)?;
+
+    if file_name.ends_with(".csv") {
+        let mut csv = String::from(
+            "finding_id,account,detector,detector_version,pattern_type,total_amount_e8s,evidence_blocks,status,notes,first_seen,last_seen,reviewed_at\n",
+        );
+        for f in &findings {
+            let blocks = f.evidence_blocks.iter().map(|b| b.to_string()).collect::<Vec<_>>().join(" ");
+            let row = [
+                f.finding_id.as_str(),
+                &f.account,
+                &f.detector,
+                &f.detector_version,
+                &f.pattern_type,
+                &f.total_amount.to_string(),
+                &blocks,
+                &f.status,
+                f.notes.as_deref().unwrap_or(""),
+                &f.first_seen,
+                &f.last_seen,
+                f.reviewed_at.as_deref().unwrap_or(""),
+            ];
+            csv.push_str(&row.iter().map(|v| csv_field(v)).collect::<Vec<_>>().join(","));
+            csv.push('\n');
+        }
+        std::fs::write(file_name, csv)?;
+    } else {
+        std::fs::write(file_name, serde_json::to_string_pretty(&findings)?)?;
+    }
+
+    println!("Exported {} findings to: {}", findings.len(), file_name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger_db::test_transaction;
+
+    #[test]
+    fn test_findings_are_deduplicated_and_keep_their_review() {
+        let path = std::env::temp_dir().join(format!("findings_test_{}.db", std::process::id()));
+        let mut db = LedgerDatabase::new(&path).unwrap();
+
+        let day = 24 * 60 * 60 * 1_000_000_000u64;
+        let mint = DbTransaction {
+            timestamp: Some(0),
+            ..test_transaction(7, "Mint", None, Some("holder"), 20_000 * 100_000_000)
+        };
+        let transfer = DbTransaction {
+            timestamp: Some(60 * day),
+            ..test_transaction(9, "Transfer", Some("holder"), Some("other"), 20_000 * 100_000_000)
+        };
+
+        // Shares the mint's timestamp but is not part of the holding period
+        let tip = DbTransaction {
+            timestamp: Some(0),
+            ..test_transaction(8, "Transfer", Some("other"), Some("holder"), 100_000_000)
+        };
+
+        let detector = PatternDetector::new();
+        let findings = analyze_account(&detector, "holder", &[mint.clone(), tip, transfer.clone()]).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].detector, "large_holding");
+        assert_eq!(findings[0].evidence_blocks, vec![7, 9]);
+        assert_eq!(db.upsert_findings(&findings).unwrap(), (1, 0));
+
+        let id = findings[0].finding_id.clone();
+        assert!(db.update_finding(&id, FindingStatus::Confirmed.as_str(), Some("checked"), "now").unwrap());
+
+        // A re-run finds the same pattern and keeps the review
+        let rerun = analyze_account(&detector, "holder", &[mint, transfer]).unwrap();
+        assert_eq!(rerun[0].finding_id, id);
+        assert_eq!(db.upsert_findings(&rerun).unwrap(), (0, 1));
+
+        let stored = db.get_findings(&FindingFilter::default()).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].status, "confirmed");
+        assert_eq!(stored[0].notes.as_deref(), Some("checked"));
+
+        drop(db);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,286 @@
+// Behavioural activity fingerprints over ledger.db
+// Profiles when an account acts (hour of day, weekday), what it typically sends and how regular
+// its pace is, then ranks other accounts by how closely they match a suspect's profile
+
+use crate::{
+    entities::{label_map, resolve_account_set},
+    ledger_db::{DbTransaction, LedgerDatabase},
+};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Datelike, Timelike};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Running sums of a log-scaled quantity
+#[derive(Debug, Clone, Copy, Default)]
+struct LogStats {
+    count: u64,
+    sum: f64,
+    sum_squares: f64,
+}
+
+impl LogStats {
+    fn add(&mut self, value: f64) {
+        let log = value.max(1.0).ln();
+        self.count += 1;
+        self.sum += log;
+        self.sum_squares += log * log;
+    }
+
+    fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum / self.count as f64
+        }
+    }
+
+    fn std_dev(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        (self.sum_squares / self.count as f64 - mean * mean).max(0.0).sqrt()
+    }
+}
+
+/// Accumulates one actor's outgoing activity; the ledger is streamed in time order
+#[derive(Debug, Clone, Default)]
+struct ProfileBuilder {
+    hours: [u64; 24],
+    weekdays: [u64; 7],
+    amounts: LogStats,
+    gaps: LogStats,
+    last_timestamp: Option<u64>,
+}
+
+impl ProfileBuilder {
+    fn observe(&mut self, timestamp: u64, amount: u64) {
+        if let Some(time) = DateTime::from_timestamp((timestamp / 1_000_000_000) as i64, 0) {
+            self.hours[time.hour() as usize] += 1;
+            self.weekdays[time.weekday().num_days_from_monday() as usize] += 1;
+        }
+        self.amounts.add(amount as f64);
+        if let Some(last) = self.last_timestamp {
+            self.gaps.add(timestamp.saturating_sub(last) as f64 / 1_000_000_000.0);
+        }
+        self.last_timestamp = Some(timestamp);
+    }
+
+    fn finish(&self, account: &str) -> ActivityProfile {
+        let total = self.amounts.count.max(1) as f64;
+        ActivityProfile {
+            account: account.to_string(),
+            transactions: self.amounts.count,
+            hours: self.hours.iter().map(|c| *c as f64 / total).collect(),
+            weekdays: self.weekdays.iter().map(|c| *c as f64 / total).collect(),
+            typical_amount: self.amounts.mean().exp() as u64,
+            amount_log_mean: self.amounts.mean(),
+            amount_log_std: self.amounts.std_dev(),
+            typical_gap_seconds: if self.gaps.count > 0 { self.gaps.mean().exp() } else { 0.0 },
+            gap_log_mean: self.gaps.mean(),
+            gap_log_std: self.gaps.std_dev(),
+        }
+    }
+}
+
+/// When and how an account transacts; only transactions the account initiated are counted
+#[derive(Debug, Clone, Serialize)]
+pub struct ActivityProfile {
+    pub account: String,
+    pub transactions: u64,
+    /// Share of transactions per UTC hour of day
+    pub hours: Vec<f64>,
+    /// Share of transactions per weekday, Monday first
+    pub weekdays: Vec<f64>,
+    /// Geometric mean amount in e8s
+    pub typical_amount: u64,
+    pub amount_log_mean: f64,
+    pub amount_log_std: f64,
+    /// Geometric mean time between transactions
+    pub typical_gap_seconds: f64,
+    pub gap_log_mean: f64,
+    /// Spread of the gaps; low values mean a regular pace
+    pub gap_log_std: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Similarity {
+    pub score: f64,
+    pub hours: f64,
+    pub weekdays: f64,
+    pub amounts: f64,
+    pub gaps: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProfileMatch {
+    pub profile: ActivityProfile,
+    pub label: Option<String>,
+    pub similarity: Similarity,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FingerprintReport {
+    pub suspect: String,
+    pub suspect_accounts: Vec<String>,
+    pub suspect_profile: ActivityProfile,
+    pub candidates_compared: usize,
+    pub matches: Vec<ProfileMatch>,
+}
+
+/// Overlap of two share histograms, 1 when identical
+fn histogram_overlap(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x.min(*y)).sum()
+}
+
+/// Bhattacharyya coefficient of two normal distributions, 1 when identical
+fn normal_overlap(mean_a: f64, std_a: f64, mean_b: f64, std_b: f64) -> f64 {
+    // A floor keeps accounts that always send the same amount comparable
+    let (var_a, var_b) = (std_a.max(0.1).powi(2), std_b.max(0.1).powi(2));
+    (2.0 * (var_a * var_b).sqrt() / (var_a + var_b)).sqrt()
+        * (-(mean_a - mean_b).powi(2) / (4.0 * (var_a + var_b))).exp()
+}
+
+/// Weighted similarity of two profiles: schedule counts most, as operators keep consistent hours
+pub fn similarity(a: &ActivityProfile, b: &ActivityProfile) -> Similarity {
+    let hours = histogram_overlap(&a.hours, &b.hours);
+    let weekdays = histogram_overlap(&a.weekdays, &b.weekdays);
+    let amounts = normal_overlap(a.amount_log_mean, a.amount_log_std, b.amount_log_mean, b.amount_log_std);
+    let gaps = normal_overlap(a.gap_log_mean, a.gap_log_std, b.gap_log_mean, b.gap_log_std);
+
+    Similarity { score: 0.4 * hours + 0.2 * weekdays + 0.2 * amounts + 0.2 * gaps, hours, weekdays, amounts, gaps }
+}
+
+/// Build profiles for every sender; accounts mapped in `groups` are profiled together under the group name
+pub fn build_profiles(
+    db: &LedgerDatabase,
+    groups: &HashMap<String, String>,
+    min_transactions: u64,
+) -> Result<Vec<ActivityProfile>> {
+    let mut builders: HashMap<String, ProfileBuilder> = HashMap::new();
+    db.for_each_transaction(None, |tx: DbTransaction| {
+        let (Some(from), Some(timestamp)) = (&tx.from_account, tx.timestamp) else { return Ok(()) };
+        if tx.operation_type != "Transfer" {
+            return Ok(());
+        }
+        let key = groups.get(from).unwrap_or(from);
+        builders.entry(key.clone()).or_default().observe(timestamp, tx.amount.unwrap_or(0));
+        Ok(())
+    })?;
+
+    Ok(builders
+        .iter()
+        .filter(|(_, b)| b.amounts.count >= min_transactions)
+        .map(|(account, b)| b.finish(account))
+        .collect())
+}
+
+/// Rank accounts by how closely their activity matches `suspect` (a SUSPECTS entry or any account set)
+pub async fn run_fingerprint(
+    db_path: &str,
+    suspect: &str,
+    candidates: Option<Vec<String>>,
+    min_transactions: u64,
+    top: usize,
+) -> Result<()> {
+    println!("===== ACTIVITY FINGERPRINT =====");
+    println!("Database: {}", db_path);
+
+    let suspect_accounts = resolve_account_set(suspect)?;
+    println!("Suspect: {} ({} accounts)", suspect, suspect_accounts.len());
+
+    let db = LedgerDatabase::new(db_path)?;
+    let key = format!("suspect:{}", suspect);
+    let groups: HashMap<String, String> = suspect_accounts.iter().map(|a| (a.clone(), key.clone())).collect();
+    let mut profiles = build_profiles(&db, &groups, min_transactions)?;
+
+    let Some(position) = profiles.iter().position(|p| p.account == key) else {
+        bail!("{} has fewer than {} outgoing transfers, nothing to compare", suspect, min_transactions);
+    };
+    let mut suspect_profile = profiles.swap_remove(position);
+    suspect_profile.account = suspect.to_string();
+
+    if let Some(candidates) = candidates {
+        let candidates: HashSet<String> = candidates.into_iter().collect();
+        profiles.retain(|p| candidates.contains(&p.account));
+    }
+    let candidates_compared = profiles.len();
+
+    let labels = label_map();
+    let mut matches: Vec<ProfileMatch> = profiles
+        .into_iter()
+        .map(|profile| ProfileMatch {
+            similarity: similarity(&suspect_profile, &profile),
+            label: labels.get(&profile.account).map(|(name, ty)| format!("{} ({})", name, ty)),
+            profile,
+        })
+        .collect();
+    matches.sort_by(|a, b| b.similarity.score.total_cmp(&a.similarity.score));
+    matches.truncate(top);
+
+    println!(
+        "Suspect profile: {} transfers, typical amount {:.2} ICP, typical gap {:.1}h",
+        suspect_profile.transactions,
+        suspect_profile.typical_amount as f64 / 100_000_000.0,
+        suspect_profile.typical_gap_seconds / 3600.0
+    );
+    println!("\nClosest of {} profiled accounts:", candidates_compared);
+    for m in matches.iter().take(20) {
+        println!(
+            "  {:.3}  {} (hours {:.2}, weekdays {:.2}, amounts {:.2}, gaps {:.2}){}",
+            m.similarity.score,
+            m.profile.account,
+            m.similarity.hours,
+            m.similarity.weekdays,
+            m.similarity.amounts,
+            m.similarity.gaps,
+            m.label.as_ref().map(|l| format!(" - {}", l)).unwrap_or_default()
+        );
+    }
+
+    let report = FingerprintReport {
+        suspect: suspect.to_string(),
+        suspect_accounts,
+        suspect_profile,
+        candidates_compared,
+        matches,
+    };
+
+    let file_name = "./fingerprint_matches.json";
+    std::fs::write(file_name, serde_json::to_string_pretty(&report)?)?;
+
+    println!("\nFingerprint matches saved to: {}", file_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(start_hour: u64, amount: u64, gap_hours: u64) -> ActivityProfile {
+        let mut builder = ProfileBuilder::default();
+        for i in 0..40 {
+            // Jitter keeps the gaps from being perfectly regular
+            let timestamp = (start_hour + i * gap_hours) * 3600 + (i % 3) * 600;
+            builder.observe(timestamp * 1_000_000_000, amount + i * 1_000);
+        }
+        builder.finish("account")
+    }
+
+    #[test]
+    fn test_similar_schedules_rank_higher() {
+        let suspect = profile(9, 50_000_000_000, 24);
+        let same_operator = profile(9, 52_000_000_000, 24);
+        let night_owl = profile(2, 50_000_000_000, 24);
+        let small_trader = profile(9, 10_000_000, 3);
+
+        let close = similarity(&suspect, &same_operator);
+        assert!(close.score > 0.9);
+        assert!(close.score > similarity(&suspect, &night_owl).score);
+        assert!(close.score > similarity(&suspect, &small_trader).score);
+        assert_eq!(similarity(&suspect, &night_owl).hours, 0.0);
+        assert!((similarity(&suspect, &suspect).score - 1.0).abs() < 1e-9);
+    }
+}
@@ -1,8 +1,8 @@
 // SQLite database for local ICP ledger data
 // Provides fast, indexed queries over millions of transactions
 
-use anyhow::Result;
-use rusqlite::{Connection, Transaction, params, OptionalExtension};
+use anyhow::{bail, Result};
+use rusqlite::{Connection, Row, Transaction, params, params_from_iter, OptionalExtension};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 use std::time::Instant;
@@ -11,10 +11,15 @@ use crate::local_ledger::LocalLedgerReader;
 use crate::pattern_addresses::get_pattern_address_list;
 
 const BATCH_SIZE: usize = 10000;
+pub const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+// Column list matching transaction_from_row
+const TRANSACTION_COLUMNS: &str =
+    "block, operation_type, from_account, to_account, amount, fee, timestamp, memo, spender, allowance";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DbTransaction {
-    pub id: u64,
+    pub id: u64, // Ledger block index, not the table's row id
     pub operation_type: String,
     pub from_account: Option<String>,
     pub to_account: Option<String>,
@@ -23,6 +28,67 @@ pub struct DbTransaction {
     pub timestamp: Option<u64>,
     pub memo: Option<u64>,
     pub spender: Option<String>,
+    pub allowance: Option<u64>,
+}
+
+/// A detector hit stored in the findings table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DbFinding {
+    pub id: i64,
+    pub finding_id: String, // Stable across re-runs: hash of account, detector and evidence
+    pub account: String,
+    pub detector: String,
+    pub detector_version: String,
+    pub pattern_type: String,
+    pub total_amount: u64,
+    pub evidence_blocks: Vec<u64>,
+    pub pattern: String, // SuspiciousPattern as JSON
+    pub status: String,  // new, confirmed, false_positive or dismissed
+    pub notes: Option<String>,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub reviewed_at: Option<String>,
+}
+
+/// Which findings `get_findings` returns; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct FindingFilter {
+    pub account: Option<String>,
+    pub detector: Option<String>,
+    pub status: Option<String>,
+}
+
+// Column list matching finding_from_row
+const FINDING_COLUMNS: &str = "id, finding_id, account, detector, detector_version, pattern_type, total_amount, \
+     evidence_blocks, pattern, status, notes, first_seen, last_seen, reviewed_at";
+
+impl DbTransaction {
+    /// Net balance change this transaction causes for the accounts matching `is_member`
+    ///
+    /// Transfers between two member accounts only cost the fee.
+    pub fn balance_delta<F: Fn(&str) -> bool>(&self, is_member: F) -> i64 {
+        let amount = self.amount.unwrap_or(0) as i64;
+        let fee = self.fee.unwrap_or(0) as i64;
+        let from_member = self.from_account.as_deref().is_some_and(&is_member);
+        let to_member = self.to_account.as_deref().is_some_and(&is_member);
+        
+        match self.operation_type.as_str() {
+            "Transfer" => {
+                let mut delta = 0;
+                if from_member {
+                    delta -= amount + fee;
+                }
+                if to_member {
+                    delta += amount;
+                }
+                delta
+            }
+            "Mint" if to_member => amount,
+            "Burn" if from_member => -amount,
+            "Approve" if from_member => -fee,
+            _ => 0,
+        }
+    }
 }
 
 pub struct LedgerDatabase {
@@ -51,6 +117,7 @@ impl LedgerDatabase {
             "
             CREATE TABLE IF NOT EXISTS transactions (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                block INTEGER,
                 operation_type TEXT NOT NULL,
                 from_account TEXT,
                 to_account TEXT,
@@ -58,7 +125,8 @@ impl LedgerDatabase {
                 fee TEXT,
                 timestamp TEXT,
                 memo TEXT,
-                spender TEXT
+                spender TEXT,
+                allowance TEXT
             );
             
             -- Indexes for fast account lookups
@@ -77,8 +145,47 @@ impl LedgerDatabase {
                 key TEXT PRIMARY KEY,
                 value TEXT
             );
+            
+            -- Pattern detector hits from offline sweeps, with their review state
+            CREATE TABLE IF NOT EXISTS findings (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                finding_id TEXT NOT NULL UNIQUE,
+                account TEXT NOT NULL,
+                detector TEXT NOT NULL,
+                detector_version TEXT NOT NULL,
+                pattern_type TEXT NOT NULL,
+                total_amount TEXT NOT NULL,
+                evidence_blocks TEXT NOT NULL,
+                pattern TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'new',
+                notes TEXT,
+                first_seen TEXT NOT NULL,
+                last_seen TEXT NOT NULL,
+                reviewed_at TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_findings_account ON findings(account);
+            CREATE INDEX IF NOT EXISTS idx_findings_detector ON findings(detector);
+            CREATE INDEX IF NOT EXISTS idx_findings_status ON findings(status);
             "
         )?;
+        
+        // Databases imported before approvals were tracked lack the allowance column
+        if self.conn.prepare("SELECT allowance FROM transactions LIMIT 0").is_err() {
+            self.conn.execute("ALTER TABLE transactions ADD COLUMN allowance TEXT", [])?;
+        }
+        
+        // Databases imported before block indexes were stored only have the row id, which matches
+        // the block only when every file was imported once and in order; guessing would put wrong
+        // blocks into every report, so such databases have to be imported again
+        if self.conn.prepare("SELECT block FROM transactions LIMIT 0").is_err() {
+            let rows: i64 = self.conn.query_row("SELECT COUNT(*) FROM transactions", [], |row| row.get(0))?;
+            if rows > 0 {
+                bail!("this database was imported without block indexes; re-run import_db into a fresh database");
+            }
+            self.conn.execute("ALTER TABLE transactions ADD COLUMN block INTEGER", [])?;
+        }
+        self.conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS idx_block ON transactions(block)", [])?;
+        
         Ok(())
     }
     
@@ -130,6 +237,8 @@ impl LedgerDatabase {
             let mut file_count = 0;
             let mut line_count = 0;
             let mut parse_errors = 0;
+            // Files hold consecutive blocks starting at the index in their name
+            let mut next_block = ledger_file.start_id;
             
             println!("  Starting to read lines...");
             
@@ -140,11 +249,13 @@ impl LedgerDatabase {
                 if line.trim().is_empty() {
                     continue;
                 }
+                let block = next_block;
+                next_block += 1;
                 
                 match serde_json::from_str::<serde_json::Value>(&line) {
                     Ok(json) => {
-                        if let Some(db_tx) = parse_transaction(&json) {
-                            // For now, don't skip - we'll use IGNORE to handle duplicates
+                        if let Some(db_tx) = parse_transaction(&json, block) {
+                            // Blocks already imported are skipped by INSERT OR IGNORE
                             
                             batch.push(db_tx);
                             
@@ -227,30 +338,188 @@ impl LedgerDatabase {
     
     /// Get all transactions for an account
     pub fn get_account_transactions(&self, account: &str) -> Result<Vec<DbTransaction>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT * FROM transactions 
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM transactions 
              WHERE from_account = ?1 OR to_account = ?1 OR spender = ?1
-             ORDER BY id"
-        )?;
+             ORDER BY block",
+            TRANSACTION_COLUMNS
+        ))?;
         
-        let transactions = stmt.query_map(params![account], |row| {
-            Ok(DbTransaction {
-                id: row.get(0)?,
-                operation_type: row.get(1)?,
-                from_account: row.get(2)?,
-                to_account: row.get(3)?,
-                amount: row.get(4)?,
-                fee: row.get(5)?,
-                timestamp: row.get(6)?,
-                memo: row.get(7)?,
-                spender: row.get(8)?,
-            })
-        })?
-        .collect::<Result<Vec<_>, _>>()?;
+        let transactions = stmt.query_map(params![account], transaction_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
         
         Ok(transactions)
     }
     
+    /// Get all transactions touching any of the given accounts, in block order
+    pub fn get_transactions_for_accounts(&self, accounts: &[String]) -> Result<Vec<DbTransaction>> {
+        if accounts.is_empty() {
+            return Ok(Vec::new());
+        }
+        
+        // Numbered placeholders so the same list can be reused for each column
+        let placeholders = (1..=accounts.len())
+            .map(|i| format!("?{}", i))
+            .collect::<Vec<_>>()
+            .join(", ");
+        
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {columns} FROM transactions 
+             WHERE from_account IN ({list}) OR to_account IN ({list}) OR spender IN ({list})
+             ORDER BY block",
+            columns = TRANSACTION_COLUMNS,
+            list = placeholders
+        ))?;
+        
+        let transactions = stmt.query_map(params_from_iter(accounts.iter()), transaction_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        
+        Ok(transactions)
+    }
+    
+    /// Stream every transaction in block order, up to and including `last_block`
+    pub fn for_each_transaction<F>(&self, last_block: Option<u64>, mut f: F) -> Result<()>
+    where
+        F: FnMut(DbTransaction) -> Result<()>,
+    {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM transactions WHERE block <= ?1 ORDER BY block",
+            TRANSACTION_COLUMNS
+        ))?;
+        
+        let mut rows = stmt.query(params![last_block.unwrap_or(i64::MAX as u64)])?;
+        while let Some(row) = rows.next()? {
+            f(transaction_from_row(row)?)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Last block at or before a timestamp (nanoseconds)
+    pub fn get_block_at_timestamp(&self, timestamp: u64) -> Result<Option<u64>> {
+        let block: Option<u64> = self.conn.query_row(
+            "SELECT MAX(block) FROM transactions WHERE CAST(timestamp AS INTEGER) <= ?1",
+            params![timestamp],
+            |row| row.get(0)
+        )?;
+        
+        Ok(block)
+    }
+    
+    /// Highest block in the database
+    pub fn get_last_block(&self) -> Result<Option<u64>> {
+        let block: Option<u64> = self.conn.query_row("SELECT MAX(block) FROM transactions", [], |row| row.get(0))?;
+        Ok(block)
+    }
+    
+    /// Latest transaction timestamp in the database (nanoseconds)
+    pub fn get_last_timestamp(&self) -> Result<Option<u64>> {
+        let timestamp: Option<u64> = self.conn.query_row(
+            "SELECT MAX(CAST(timestamp AS INTEGER)) FROM transactions",
+            [],
+            |row| row.get(0)
+        )?;
+        
+        Ok(timestamp)
+    }
+    
+    /// Every account that sent or received funds
+    pub fn get_all_accounts(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT from_account FROM transactions WHERE from_account IS NOT NULL
+             UNION
+             SELECT to_account FROM transactions WHERE to_account IS NOT NULL"
+        )?;
+        
+        let accounts = stmt.query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        
+        Ok(accounts)
+    }
+    
+    /// Stored findings matching `filter`, largest first
+    pub fn get_findings(&self, filter: &FindingFilter) -> Result<Vec<DbFinding>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM findings
+             WHERE (?1 IS NULL OR account = ?1) AND (?2 IS NULL OR detector = ?2) AND (?3 IS NULL OR status = ?3)
+             ORDER BY CAST(total_amount AS INTEGER) DESC, id",
+            FINDING_COLUMNS
+        ))?;
+        
+        let findings = stmt.query_map(params![filter.account, filter.detector, filter.status], finding_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        
+        Ok(findings)
+    }
+    
+    /// Store findings, deduplicating on `finding_id`
+    ///
+    /// A finding seen before keeps its id, status, notes and first_seen; only its pattern,
+    /// detector version and last_seen are refreshed. Returns (inserted, updated).
+    pub fn upsert_findings(&mut self, findings: &[DbFinding]) -> Result<(usize, usize)> {
+        let tx = self.conn.transaction()?;
+        let mut inserted = 0;
+        let mut updated = 0;
+        
+        for finding in findings {
+            let changed = tx.execute(
+                "UPDATE findings SET detector_version = ?2, pattern_type = ?3, total_amount = ?4,
+                     evidence_blocks = ?5, pattern = ?6, last_seen = ?7
+                 WHERE finding_id = ?1",
+                params![
+                    finding.finding_id,
+                    finding.detector_version,
+                    finding.pattern_type,
+                    finding.total_amount.to_string(),
+                    serde_json::to_string(&finding.evidence_blocks)?,
+                    finding.pattern,
+                    finding.last_seen,
+                ],
+            )?;
+            
+            if changed > 0 {
+                updated += 1;
+                continue;
+            }
+            
+            tx.execute(
+                &format!(
+                    "INSERT INTO findings ({}) VALUES (NULL, ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                    FINDING_COLUMNS
+                ),
+                params![
+                    finding.finding_id,
+                    finding.account,
+                    finding.detector,
+                    finding.detector_version,
+                    finding.pattern_type,
+                    finding.total_amount.to_string(),
+                    serde_json::to_string(&finding.evidence_blocks)?,
+                    finding.pattern,
+                    finding.status,
+                    finding.notes,
+                    finding.first_seen,
+                    finding.last_seen,
+                    finding.reviewed_at,
+                ],
+            )?;
+            inserted += 1;
+        }
+        tx.commit()?;
+        
+        Ok((inserted, updated))
+    }
+    
+    /// Record a review; notes are only replaced when given. Returns false for an unknown id
+    pub fn update_finding(&self, finding_id: &str, status: &str, notes: Option<&str>, reviewed_at: &str) -> Result<bool> {
+        let changed = self.conn.execute(
+            "UPDATE findings SET status = ?2, notes = COALESCE(?3, notes), reviewed_at = ?4 WHERE finding_id = ?1",
+            params![finding_id, status, notes, reviewed_at],
+        )?;
+        
+        Ok(changed > 0)
+    }
+    
     /// Get account balance at a specific timestamp
     pub fn get_balance_at_timestamp(&self, account: &str, timestamp: u64) -> Result<i64> {
         let received: i64 = self.conn.query_row(
@@ -392,8 +661,8 @@ impl LedgerDatabase {
         let max_timestamp = max_timestamp.unwrap_or(0);
         
         // Convert nanoseconds to days for binning
-        let min_day = min_timestamp / (24 * 60 * 60 * 1_000_000_000);
-        let max_day = max_timestamp / (24 * 60 * 60 * 1_000_000_000);
+        let min_day = min_timestamp / NANOS_PER_DAY;
+        let max_day = max_timestamp / NANOS_PER_DAY;
         
         println!("Generating daily balances for {} addresses", pattern_addresses.len());
         println!("Date range: {} to {} days", min_day, max_day);
@@ -449,7 +718,7 @@ impl LedgerDatabase {
             
             // Parse timestamp
             let timestamp: u64 = timestamp_str.parse().unwrap_or(0);
-            let day = timestamp / (24 * 60 * 60 * 1_000_000_000);
+            let day = timestamp / NANOS_PER_DAY;
             
             // Fill in missing days with current balance
             while last_day < day {
@@ -499,15 +768,60 @@ impl LedgerDatabase {
     }
 }
 
-/// Parse a JSON transaction into DbTransaction
-fn parse_transaction(json: &serde_json::Value) -> Option<DbTransaction> {
-    // Generate a pseudo-id from timestamp if not present
+/// Read a row selected with TRANSACTION_COLUMNS
+///
+/// Amounts and timestamps are stored as TEXT, so they are parsed here
+/// rather than read as integers.
+fn transaction_from_row(row: &Row) -> rusqlite::Result<DbTransaction> {
+    let parse = |idx: usize| -> rusqlite::Result<Option<u64>> {
+        Ok(row.get::<_, Option<String>>(idx)?.and_then(|s| s.parse().ok()))
+    };
+    
+    Ok(DbTransaction {
+        id: row.get(0)?,
+        operation_type: row.get(1)?,
+        from_account: row.get(2)?,
+        to_account: row.get(3)?,
+        amount: parse(4)?,
+        fee: parse(5)?,
+        timestamp: parse(6)?,
+        memo: parse(7)?,
+        spender: row.get(8)?,
+        allowance: parse(9)?,
+    })
+}
+
+fn finding_from_row(row: &Row) -> rusqlite::Result<DbFinding> {
+    Ok(DbFinding {
+        id: row.get(0)?,
+        finding_id: row.get(1)?,
+        account: row.get(2)?,
+        detector: row.get(3)?,
+        detector_version: row.get(4)?,
+        pattern_type: row.get(5)?,
+        total_amount: row.get::<_, String>(6)?.parse().unwrap_or(0),
+        evidence_blocks: serde_json::from_str(&row.get::<_, String>(7)?).unwrap_or_default(),
+        pattern: row.get(8)?,
+        status: row.get(9)?,
+        notes: row.get(10)?,
+        first_seen: row.get(11)?,
+        last_seen: row.get(12)?,
+        reviewed_at: row.get(13)?,
+    })
+}
+
+/// Parse a JSON block into DbTransaction
+///
+/// `block` is the block's position in the ledger; an explicit index in the JSON takes precedence.
+fn parse_transaction(json: &serde_json::Value, block: u64) -> Option<DbTransaction> {
     let timestamp = json.get("timestamp")
         .and_then(|v| v.get("timestamp_nanos"))
         .and_then(|v| v.as_u64())?;
     
-    // Use timestamp as ID since these files don't have explicit IDs
-    let id = timestamp / 1_000_000; // Convert nanos to millis for smaller number
+    let id = ["block_index", "index"]
+        .iter()
+        .find_map(|key| json.get(*key).and_then(|v| v.as_u64()))
+        .unwrap_or(block);
     
     let transaction = json.get("transaction")?;
     let operation = transaction.get("operation")?;
@@ -536,6 +850,10 @@ fn parse_transaction(json: &serde_json::Value) -> Option<DbTransaction> {
         .and_then(|v| v.as_str())
         .map(|s| s.to_string());
     
+    let allowance = operation.get("allowance")
+        .and_then(|v| v.get("e8s"))
+        .and_then(|v| v.as_u64());
+    
     Some(DbTransaction {
         id,
         operation_type: operation_type.to_string(),
@@ -546,19 +864,21 @@ fn parse_transaction(json: &serde_json::Value) -> Option<DbTransaction> {
         timestamp: Some(timestamp),
         memo,
         spender,
+        allowance,
     })
 }
 
 /// Insert a batch of transactions
 fn insert_batch(tx: &Transaction, batch: &[DbTransaction]) -> Result<()> {
     let mut stmt = tx.prepare_cached(
-        "INSERT INTO transactions 
-         (operation_type, from_account, to_account, amount, fee, timestamp, memo, spender)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
+        "INSERT OR IGNORE INTO transactions 
+         (block, operation_type, from_account, to_account, amount, fee, timestamp, memo, spender, allowance)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)"
     )?;
     
     for transaction in batch {
         stmt.execute(params![
+            transaction.id,
             transaction.operation_type,
             transaction.from_account,
             transaction.to_account,
@@ -567,6 +887,7 @@ fn insert_batch(tx: &Transaction, batch: &[DbTransaction]) -> Result<()> {
             transaction.timestamp.map(|v| v.to_string()),
             transaction.memo.map(|v| v.to_string()),
             transaction.spender,
+            transaction.allowance.map(|v| v.to_string()),
         ])?;
     }
     
@@ -590,4 +911,81 @@ pub async fn run_daily_balance_generation(db_path: &str) -> Result<()> {
     println!("Daily balance data saved to: {}", output_path);
     
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Test fixture: a transaction without a fee, timestamped with its block index
+#[cfg(test)]
+pub fn test_transaction(id: u64, operation_type: &str, from: Option<&str>, to: Option<&str>, amount: u64) -> DbTransaction {
+    DbTransaction {
+        id,
+        operation_type: operation_type.to_string(),
+        from_account: from.map(String::from),
+        to_account: to.map(String::from),
+        amount: Some(amount),
+        fee: Some(0),
+        timestamp: Some(id),
+        memo: None,
+        spender: None,
+        allowance: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn block_json(from: &str, to: &str, amount: u64, timestamp: u64) -> String {
+        serde_json::json!({
+            "timestamp": { "timestamp_nanos": timestamp },
+            "transaction": {
+                "memo": 0,
+                "operation": { "type": "Transfer", "from": from, "to": to, "amount": { "e8s": amount }, "fee": { "e8s": 10_000 } }
+            }
+        })
+        .to_string()
+    }
+    
+    #[test]
+    fn test_import_stores_block_index() {
+        let dir = std::env::temp_dir().join(format!("ledger_import_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Blocks come from the file name, so a gap between files must not shift them
+        std::fs::write(
+            dir.join("icp_ledger_100_102.jsonl"),
+            format!("{}\n{}\n", block_json("a", "b", 5, 2_000), block_json("b", "c", 3, 3_000)),
+        ).unwrap();
+        std::fs::write(dir.join("icp_ledger_0_1.jsonl"), format!("{}\n", block_json("x", "a", 9, 1_000))).unwrap();
+        
+        let path = dir.join("ledger.db");
+        let mut db = LedgerDatabase::new(&path).unwrap();
+        db.import_from_jsonl(&dir).unwrap();
+        
+        let mut blocks = Vec::new();
+        db.for_each_transaction(None, |tx| {
+            blocks.push(tx.id);
+            Ok(())
+        }).unwrap();
+        assert_eq!(blocks, vec![0, 100, 101]);
+        assert_eq!(db.get_last_block().unwrap(), Some(101));
+        assert_eq!(db.get_block_at_timestamp(2_500).unwrap(), Some(100));
+        
+        drop(db);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+    
+    #[test]
+    fn test_database_without_block_indexes_is_refused() {
+        let path = std::env::temp_dir().join(format!("ledger_legacy_test_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE transactions (id INTEGER PRIMARY KEY AUTOINCREMENT, operation_type TEXT NOT NULL,
+                from_account TEXT, to_account TEXT, amount TEXT, fee TEXT, timestamp TEXT, memo TEXT, spender TEXT);
+             INSERT INTO transactions (operation_type, to_account, amount) VALUES ('Mint', 'a', '5');",
+        ).unwrap();
+        drop(conn);
+        
+        assert!(LedgerDatabase::new(&path).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,17 +1,38 @@
 pub mod addresses;
+pub mod amounts;
+pub mod attribution;
+pub mod centrality;
+pub mod clustering;
+pub mod communities;
+pub mod coordination;
+pub mod cycles;
+pub mod distribution;
+pub mod entities;
 pub mod filter_analysis;
+pub mod findings;
+pub mod fingerprint;
 pub mod helper;
 pub mod ledger_db;
 pub mod local_ledger;
+pub mod max_flow;
 pub mod network_tracer;
+pub mod paths;
 pub mod pattern_addresses;
 pub mod pattern_detector;
+pub mod peel_chain;
+pub mod replay;
+pub mod risk;
+pub mod snapshot;
+pub mod supply;
+pub mod taint;
 pub mod transactions;
+pub mod transfer_graph;
 
-use addresses::{CEXES, DEFI, FOUNDATION, IDENTIFIED, NODE_PROVIDERS, SNSES, SPAMMERS, SUSPECTS};
+use addresses::{CEXES, SUSPECTS};
 use candid::Principal;
 use chrono::{DateTime, Utc};
 use derive_more::Display;
+use distribution::balance_buckets;
 use filter_analysis::create_filtered_report;
 use helper::principal_to_account_id;
 use ic_agent::Agent;
@@ -95,27 +116,40 @@ pub enum Type {
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
     let mode = args.get(1).map(|s| s.as_str()).unwrap_or("graph_data");
-    
-    let agent = Agent::builder().with_url(IC_URL).build()?;
-
-    // Initialize the agent (fetch root key in development)
-    agent.fetch_root_key().await?;
 
+    // Only the modes that query the IC connect; ledger.db modes work offline
     match mode {
-        "graph_data" => run_graph_data_mode(&agent).await?,
-        "analyze_patterns" => run_pattern_analysis_mode(&agent).await?,
+        "graph_data" => run_graph_data_mode(&connect().await?).await?,
+        "analyze_patterns" => {
+            let detector = pattern_detector_from_args(&args)?;
+            if let Some(db_path) = flag_value(&args, "--db") {
+                let accounts = flag_value(&args, "--accounts").map(entities::resolve_account_set).transpose()?;
+                let threads = match flag_value(&args, "--threads") {
+                    Some(value) => value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or_else(|| anyhow::anyhow!("invalid --threads '{}': expected a positive integer", value))?,
+                    None => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+                };
+                findings::run_pattern_sweep(db_path, detector, accounts, threads)?;
+            } else {
+                run_pattern_analysis_mode(&connect().await?, detector).await?;
+            }
+        }
         "analyze_account" => {
             if let Some(account_hex) = args.get(2) {
-                run_single_account_analysis(&agent, account_hex).await?;
+                let detector = pattern_detector_from_args(&args)?;
+                run_single_account_analysis(&connect().await?, account_hex, detector).await?;
             } else {
                 eprintln!("Usage: cargo run analyze_account <account_hex>");
                 std::process::exit(1);
             }
         }
-        "trace_network" => run_network_trace(&agent).await?,
-        "analyze_seeds" => run_seed_analysis(&agent).await?,
-        "trace_funds" => run_funds_trace(&agent).await?,
-        "trace_225a2" => run_225a2_complete_trace(&agent).await?,
+        "trace_network" => run_network_trace(&connect().await?).await?,
+        "analyze_seeds" => run_seed_analysis(&connect().await?).await?,
+        "trace_funds" => run_funds_trace(&connect().await?).await?,
+        "trace_225a2" => run_225a2_complete_trace(&connect().await?).await?,
         "filter_analysis" => {
             create_filtered_report()?;
         }
@@ -145,8 +179,259 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let db_path = args.get(2).map(|s| s.as_str()).unwrap_or("./ledger.db");
             run_daily_balance_generation(db_path).await?;
         }
+        "rich_list" => {
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            let top_n = flag_value(&args, "--top").and_then(|s| s.parse().ok()).unwrap_or(100);
+            distribution::run_rich_list(db_path, flag_value(&args, "--at"), top_n).await?;
+        }
+        "snapshot" => {
+            let Some(block) = flag_value(&args, "--block").and_then(|s| s.parse().ok()) else {
+                eprintln!("Usage: cargo run snapshot --block <N> [--format csv|parquet] [--db path]");
+                std::process::exit(1);
+            };
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            let format = snapshot::SnapshotFormat::parse(flag_value(&args, "--format").unwrap_or("csv"))?;
+            snapshot::run_snapshot(db_path, block, format).await?;
+        }
+        "replay" => {
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            let last_block = flag_value(&args, "--to").and_then(|s| s.parse().ok());
+            let checkpoint = flag_value(&args, "--checkpoint").and_then(|s| s.parse().ok()).unwrap_or(1_000_000);
+            let reported_supply = flag_value(&args, "--supply").and_then(|s| s.parse().ok());
+            replay::run_replay(db_path, last_block, checkpoint, reported_supply).await?;
+        }
+        "supply_series" => {
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            supply::run_supply_series(db_path).await?;
+        }
+        "find_paths" => {
+            if let (Some(from), Some(to)) = (args.get(2), args.get(3)) {
+                let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+                let filter = transfer_graph::GraphFilter {
+                    min_amount: flag_value(&args, "--min-amount").and_then(|s| s.parse().ok()).unwrap_or(0),
+                    window: transfer_graph::TimeWindow::parse(flag_value(&args, "--since"), flag_value(&args, "--until"))?,
+                };
+                let max_hops = flag_value(&args, "--max-hops").and_then(|s| s.parse().ok()).unwrap_or(6);
+                let k = flag_value(&args, "--k").and_then(|s| s.parse().ok()).unwrap_or(5);
+                paths::run_find_paths(db_path, from, to, filter, max_hops, k).await?;
+            } else {
+                eprintln!("Usage: cargo run find_paths <from_hex> <to_hex> [--k N] [--max-hops N] [--min-amount e8s] [--since YYYY-MM-DD] [--until YYYY-MM-DD] [--db path]");
+                std::process::exit(1);
+            }
+        }
+        "max_flow" => {
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            let sources = entities::resolve_account_set(flag_value(&args, "--sources").unwrap_or("seeds"))?;
+            let sinks = entities::resolve_account_set(flag_value(&args, "--sinks").unwrap_or("cex"))?;
+            let filter = transfer_graph::GraphFilter {
+                min_amount: flag_value(&args, "--min-amount").and_then(|s| s.parse().ok()).unwrap_or(0),
+                window: transfer_graph::TimeWindow::parse(flag_value(&args, "--since"), flag_value(&args, "--until"))?,
+            };
+            max_flow::run_max_flow(db_path, sources, sinks, filter).await?;
+        }
+        "trace_forward" => {
+            let Some(block) = flag_value(&args, "--block").and_then(|s| s.parse().ok()) else {
+                eprintln!("Usage: cargo run trace_forward --block <N> [--policy fifo|lifo|pro-rata|poison] [--stop exchanges|labelled|none] [--db path]");
+                std::process::exit(1);
+            };
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            let policy = taint::Policy::parse(flag_value(&args, "--policy").unwrap_or("fifo"))?;
+            let stop_at = taint::StopAt::parse(flag_value(&args, "--stop").unwrap_or("labelled"))?;
+            taint::run_trace_forward(db_path, block, policy, stop_at).await?;
+        }
+        "attribute" => {
+            if let Some(account) = args.get(2) {
+                let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+                let policy = taint::Policy::parse(flag_value(&args, "--policy").unwrap_or("fifo"))?;
+                let hops = flag_value(&args, "--hops").and_then(|s| s.parse().ok()).unwrap_or(3);
+                let min_amount = flag_value(&args, "--min-amount").and_then(|s| s.parse().ok()).unwrap_or(100_000_000);
+                attribution::run_attribution(db_path, account, flag_value(&args, "--at"), policy, hops, min_amount).await?;
+            } else {
+                eprintln!("Usage: cargo run attribute <account_hex> [--at <date|block>] [--policy fifo|lifo|pro-rata|poison] [--hops N] [--min-amount e8s] [--db path]");
+                std::process::exit(1);
+            }
+        }
+        "taint" => {
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            let seeds = entities::resolve_account_set(flag_value(&args, "--seeds").unwrap_or("seeds"))?;
+            let policy = taint::Policy::parse(flag_value(&args, "--policy").unwrap_or("haircut"))?;
+            let stop_at = taint::StopAt::parse(flag_value(&args, "--stop").unwrap_or("exchanges"))?;
+            let min_tainted = flag_value(&args, "--min-taint").and_then(|s| s.parse().ok()).unwrap_or(100_000_000);
+            taint::run_taint(db_path, seeds, policy, stop_at, min_tainted).await?;
+        }
+        "cluster" => {
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            let min_confidence = flag_value(&args, "--min-confidence").and_then(|s| s.parse().ok()).unwrap_or(0.5);
+            let mut config = clustering::ClusterConfig::default();
+            if let Some(min_amount) = flag_value(&args, "--min-amount").and_then(|s| s.parse().ok()) {
+                config.min_amount = min_amount;
+            }
+            clustering::run_clustering(db_path, config, min_confidence).await?;
+        }
+        "communities" => {
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            let filter = transfer_graph::GraphFilter {
+                min_amount: flag_value(&args, "--min-amount").and_then(|s| s.parse().ok()).unwrap_or(100_000_000),
+                window: transfer_graph::TimeWindow::parse(flag_value(&args, "--since"), flag_value(&args, "--until"))?,
+            };
+            communities::run_communities(db_path, filter).await?;
+        }
+        "centrality" => {
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            let filter = transfer_graph::GraphFilter {
+                min_amount: flag_value(&args, "--min-amount").and_then(|s| s.parse().ok()).unwrap_or(100_000_000),
+                window: transfer_graph::TimeWindow::parse(flag_value(&args, "--since"), flag_value(&args, "--until"))?,
+            };
+            let samples = flag_value(&args, "--samples").and_then(|s| s.parse().ok()).unwrap_or(1000);
+            let top_n = flag_value(&args, "--top").and_then(|s| s.parse().ok()).unwrap_or(100);
+            centrality::run_centrality(db_path, filter, samples, top_n).await?;
+        }
+        "cycles" => {
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            let filter = transfer_graph::GraphFilter {
+                min_amount: flag_value(&args, "--min-amount").and_then(|s| s.parse().ok()).unwrap_or(1_000_000_000),
+                window: transfer_graph::TimeWindow::parse(flag_value(&args, "--since"), flag_value(&args, "--until"))?,
+            };
+            let defaults = cycles::CycleConfig::default();
+            let config = cycles::CycleConfig {
+                max_hops: flag_value(&args, "--max-hops").and_then(|s| s.parse().ok()).unwrap_or(defaults.max_hops),
+                amount_tolerance: flag_value(&args, "--tolerance")
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(defaults.amount_tolerance),
+                max_duration_nanos: flag_value(&args, "--max-days")
+                    .and_then(|s| s.parse::<u64>().ok())
+                    .map(|days| days * ledger_db::NANOS_PER_DAY)
+                    .unwrap_or(defaults.max_duration_nanos),
+                ..defaults
+            };
+            cycles::run_cycles(db_path, filter, config).await?;
+        }
+        "amounts" => {
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            let defaults = amounts::AmountConfig::default();
+            let config = amounts::AmountConfig {
+                min_amount: flag_value(&args, "--min-amount").and_then(|s| s.parse().ok()).unwrap_or(defaults.min_amount),
+                max_accounts_per_amount: flag_value(&args, "--max-accounts")
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(defaults.max_accounts_per_amount),
+                thresholds: flag_value(&args, "--thresholds")
+                    .map(amounts::AmountConfig::parse_thresholds)
+                    .transpose()?
+                    .unwrap_or(defaults.thresholds),
+                threshold_margin: flag_value(&args, "--margin").and_then(|s| s.parse().ok()).unwrap_or(defaults.threshold_margin),
+                window: transfer_graph::TimeWindow::parse(flag_value(&args, "--since"), flag_value(&args, "--until"))?,
+                ..defaults
+            };
+            amounts::run_amount_patterns(db_path, config).await?;
+        }
+        "coordination" => {
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            let defaults = coordination::CoordinationConfig::default();
+            let config = coordination::CoordinationConfig {
+                window_nanos: flag_value(&args, "--window")
+                    .and_then(|s| s.parse::<u64>().ok())
+                    .map(|seconds| seconds * 1_000_000_000)
+                    .unwrap_or(defaults.window_nanos),
+                min_amount: flag_value(&args, "--min-amount").and_then(|s| s.parse().ok()).unwrap_or(defaults.min_amount),
+                min_co_occurrences: flag_value(&args, "--min-count")
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(defaults.min_co_occurrences),
+                max_p_value: flag_value(&args, "--max-p").and_then(|s| s.parse().ok()).unwrap_or(defaults.max_p_value),
+                window: transfer_graph::TimeWindow::parse(flag_value(&args, "--since"), flag_value(&args, "--until"))?,
+                ..defaults
+            };
+            coordination::run_coordination(db_path, config).await?;
+        }
+        "peel_chains" => {
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            let filter = transfer_graph::GraphFilter {
+                min_amount: flag_value(&args, "--min-amount").and_then(|s| s.parse().ok()).unwrap_or(10_000_000),
+                window: transfer_graph::TimeWindow::parse(flag_value(&args, "--since"), flag_value(&args, "--until"))?,
+            };
+            let defaults = peel_chain::PeelConfig::default();
+            let config = peel_chain::PeelConfig {
+                min_length: flag_value(&args, "--min-length").and_then(|s| s.parse().ok()).unwrap_or(defaults.min_length),
+                min_start_amount: flag_value(&args, "--min-start")
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(defaults.min_start_amount),
+                ..defaults
+            };
+            let starts = flag_value(&args, "--start").map(entities::resolve_account_set).transpose()?;
+            peel_chain::run_peel_chains(db_path, filter, config, starts).await?;
+        }
+        "risk" => {
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            let accounts = flag_value(&args, "--accounts").map(entities::resolve_account_set).transpose()?;
+            let weights = risk::RiskWeights::parse(flag_value(&args, "--weights").unwrap_or(""))?;
+            let taint_path = flag_value(&args, "--taint").unwrap_or("./taint_report.json");
+            risk::run_risk_scores(db_path, accounts, taint_path, weights).await?;
+        }
+        "fingerprint" => {
+            let Some(suspect) = flag_value(&args, "--suspect") else {
+                eprintln!("Usage: cargo run fingerprint --suspect <name|account> [--candidates spec] [--min-tx N] [--top N] [--db path]");
+                std::process::exit(1);
+            };
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            let candidates = flag_value(&args, "--candidates").map(entities::resolve_account_set).transpose()?;
+            let min_transactions = flag_value(&args, "--min-tx").and_then(|s| s.parse().ok()).unwrap_or(10);
+            let top = flag_value(&args, "--top").and_then(|s| s.parse().ok()).unwrap_or(100);
+            fingerprint::run_fingerprint(db_path, suspect, candidates, min_transactions, top).await?;
+        }
+        "findings" => {
+            let db_path = flag_value(&args, "--db").unwrap_or("./ledger.db");
+            let filter = ledger_db::FindingFilter {
+                account: flag_value(&args, "--account").map(|s| s.to_string()),
+                detector: flag_value(&args, "--detector").map(|s| s.to_string()),
+                status: flag_value(&args, "--status")
+                    .map(findings::FindingStatus::parse)
+                    .transpose()?
+                    .map(|s| s.as_str().to_string()),
+            };
+            match args.get(2).map(|s| s.as_str()) {
+                Some("list") => findings::run_list_findings(db_path, filter).await?,
+                Some("update") => {
+                    let (Some(finding_id), Some(status)) = (args.get(3), flag_value(&args, "--status")) else {
+                        eprintln!("Usage: cargo run findings update <finding_id> --status new|confirmed|false_positive|dismissed [--notes text] [--db path]");
+                        std::process::exit(1);
+                    };
+                    let status = findings::FindingStatus::parse(status)?;
+                    findings::run_update_finding(db_path, finding_id, status, flag_value(&args, "--notes")).await?;
+                }
+                Some("export") => {
+                    let out = flag_value(&args, "--out").unwrap_or("./findings_export.json");
+                    findings::run_export_findings(db_path, filter, out).await?;
+                }
+                _ => {
+                    eprintln!("Usage: cargo run findings list|update|export [--account hex] [--detector name] [--status s] [--db path]");
+                    std::process::exit(1);
+                }
+            }
+        }
+        "detectors" => {
+            let detector = pattern_detector_from_args(&args)?;
+            println!("===== PATTERN DETECTORS =====");
+            for info in detector.list() {
+                println!("\n{} v{} [{}]", info.name, info.version, if info.enabled { "enabled" } else { "disabled" });
+                println!("  {}", info.description);
+                for param in &info.params {
+                    println!("  --params {}.{}={}  ({})", info.name, param.name, param.value, param.description);
+                }
+                for field in &info.evidence {
+                    println!("  evidence: {} - {}", field.field, field.description);
+                }
+            }
+        }
+        "entity" => {
+            if let Some(query) = args.get(2) {
+                let db_path = args.get(3).map(|s| s.as_str()).unwrap_or("./ledger.db");
+                entities::run_entity_report(query, db_path).await?;
+            } else {
+                eprintln!("Usage: cargo run entity <name|category> [db_path]");
+                std::process::exit(1);
+            }
+        }
         _ => {
-            eprintln!("Unknown mode: {}. Use 'graph_data', 'analyze_patterns', 'analyze_account <hex>', 'trace_network', 'analyze_seeds', 'trace_funds', 'trace_225a2', 'filter_analysis', 'local_ledger <account_hex>', 'import_db [ledger_directory] [db_path]', 'query_db <account_hex> [db_path]', or 'daily_balances [db_path]'", mode);
+            eprintln!("Unknown mode: {}. Use 'graph_data', 'analyze_patterns [--db path [--accounts spec] [--threads N]] [--detectors a,b] [--params ...]', 'analyze_account <hex>', 'trace_network', 'analyze_seeds', 'trace_funds', 'trace_225a2', 'filter_analysis', 'local_ledger <account_hex>', 'import_db [ledger_directory] [db_path]', 'query_db <account_hex> [db_path]', 'daily_balances [db_path]', 'entity <name|category> [db_path]', 'rich_list [--at <date|block>] [--top N] [--db path]', 'snapshot --block <N> [--format csv|parquet] [--db path]', 'replay [--to <block>] [--checkpoint N] [--supply e8s] [--db path]', 'supply_series [--db path]', 'find_paths <from_hex> <to_hex> [--k N] [--max-hops N] [--min-amount e8s] [--since date] [--until date] [--db path]', 'max_flow [--sources <set>] [--sinks <set>] [--min-amount e8s] [--since date] [--until date] [--db path]', 'trace_forward --block <N> [--policy fifo|lifo|pro-rata|poison] [--stop exchanges|labelled|none] [--db path]', 'attribute <account_hex> [--at <date|block>] [--policy P] [--hops N] [--min-amount e8s] [--db path]', 'taint [--seeds seeds|suspects|<file>] [--policy haircut|poison|fifo|lifo] [--stop exchanges|labelled|none] [--min-taint e8s] [--db path]', 'cluster [--min-confidence X] [--min-amount e8s] [--db path]', 'communities [--min-amount e8s] [--since date] [--until date] [--db path]', 'centrality [--top N] [--samples N] [--min-amount e8s] [--since date] [--until date] [--db path]', 'cycles [--max-hops N] [--tolerance 0.1] [--max-days N] [--min-amount e8s] [--since date] [--until date] [--db path]', 'peel_chains [--start accounts] [--min-length N] [--min-start e8s] [--min-amount e8s] [--since date] [--until date] [--db path]', 'amounts [--thresholds icp,icp] [--margin 0.05] [--max-accounts N] [--min-amount e8s] [--since date] [--until date] [--db path]', 'coordination [--window secs] [--min-count N] [--max-p X] [--min-amount e8s] [--since date] [--until date] [--db path]', 'detectors [--detectors a,b] [--params detector.param=value,...]', 'fingerprint --suspect <name|account> [--candidates spec] [--min-tx N] [--top N] [--db path]', 'findings list|update <id> --status s [--notes text]|export [--out file.json|file.csv] [--account hex] [--detector name] [--status s] [--db path]', or 'risk [--accounts spec] [--taint path] [--weights factor=w,...] [--db path]'", mode);
             std::process::exit(1);
         }
     }
@@ -154,6 +439,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+// flag_value
+// value following a `--flag` argument
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1)).map(|s| s.as_str())
+}
+
+// connect
+// agent for the modes that query the IC
+async fn connect() -> Result<Agent, Box<dyn std::error::Error>> {
+    let agent = Agent::builder().with_url(IC_URL).build()?;
+
+    // Initialize the agent (fetch root key in development)
+    agent.fetch_root_key().await?;
+
+    Ok(agent)
+}
+
 async fn run_graph_data_mode(agent: &Agent) -> Result<(), Box<dyn std::error::Error>> {
     let entries = get_entries();
 
@@ -184,10 +486,21 @@ async fn run_graph_data_mode(agent: &Agent) -> Result<(), Box<dyn std::error::Er
     Ok(())
 }
 
-async fn run_pattern_analysis_mode(agent: &Agent) -> Result<(), Box<dyn std::error::Error>> {
+/// Build the detector registry, applying `--detectors a,b` and `--params detector.param=value,...`
+fn pattern_detector_from_args(args: &[String]) -> anyhow::Result<PatternDetector> {
+    let mut detector = PatternDetector::new();
+    if let Some(names) = flag_value(args, "--detectors") {
+        detector.enable_only(&names.split(',').map(str::trim).collect::<Vec<_>>())?;
+    }
+    if let Some(settings) = flag_value(args, "--params") {
+        detector.configure_all(settings)?;
+    }
+    Ok(detector)
+}
+
+async fn run_pattern_analysis_mode(agent: &Agent, detector: PatternDetector) -> Result<(), Box<dyn std::error::Error>> {
     println!("Analyzing transaction patterns for suspicious activity...");
     
-    let detector = PatternDetector::new();
     let mut all_patterns = Vec::new();
     
     // Analyze suspect accounts
@@ -206,6 +519,8 @@ async fn run_pattern_analysis_mode(agent: &Agent) -> Result<(), Box<dyn std::err
                             to: tx.to.clone(),
                             amount: tx.amount,
                             timestamp: tx.timestamp,
+                            block: tx.id,
+                            fee: tx.fee,
                         }
                     }).collect();
                     
@@ -232,11 +547,13 @@ async fn run_pattern_analysis_mode(agent: &Agent) -> Result<(), Box<dyn std::err
     Ok(())
 }
 
-async fn run_single_account_analysis(agent: &Agent, account_hex: &str) -> Result<(), Box<dyn std::error::Error>> {
+async fn run_single_account_analysis(
+    agent: &Agent,
+    account_hex: &str,
+    detector: PatternDetector,
+) -> Result<(), Box<dyn std::error::Error>> {
     println!("Analyzing account: {}", account_hex);
     
-    let detector = PatternDetector::new();
-    
     // Fetch transactions for this account
     let account_data = AccountData::new("Target Account", &[account_hex], Type::Suspect);
     match fetch_with_retry(account_data, agent, 3).await {
@@ -250,6 +567,8 @@ async fn run_single_account_analysis(agent: &Agent, account_hex: &str) -> Result
                     to: tx.to.clone(),
                     amount: tx.amount,
                     timestamp: tx.timestamp,
+                    block: tx.id,
+                    fee: tx.fee,
                 }
             }).collect();
             
@@ -293,7 +612,9 @@ async fn run_network_trace(agent: &Agent) -> Result<(), Box<dyn std::error::Erro
     let max_depth = 3; // How many hops to follow
     let min_amount_threshold = 100_000_000; // 1 ICP minimum to follow
     
-    let network = tracer.trace_network(agent, max_depth, min_amount_threshold).await?;
+    let mut network = tracer.trace_network(agent, max_depth, min_amount_threshold).await?;
+    let communities = communities::assign_communities(&mut network);
+    let centrality = centrality::assign_centrality(&mut network, 1000, 20);
     
     // Save network data
     let json_string = serde_json::to_string_pretty(&network)?;
@@ -307,6 +628,9 @@ async fn run_network_trace(agent: &Agent) -> Result<(), Box<dyn std::error::Erro
     println!("Total connections: {}", network.edges.len());
     println!("Total balance held: {} ICP", network.total_balance as f64 / 100_000_000.0);
     println!("Suspicious accounts: {}", network.suspicious_accounts.len());
+    println!("Communities: {} (modularity {:.3})", communities.communities.len(), communities.modularity);
+    let top_hubs: Vec<&str> = centrality.hubs.iter().take(5).map(|a| &a[..8]).collect();
+    println!("Top hubs by PageRank: {}", top_hubs.join(", "));
     
     // List top balance holders
     let mut sorted_nodes: Vec<_> = network.nodes.values().collect();
@@ -325,7 +649,11 @@ async fn run_network_trace(agent: &Agent) -> Result<(), Box<dyn std::error::Erro
         }
     }
     
+    let centrality_file = "./../graph/public/network_centrality.json";
+    std::fs::write(centrality_file, serde_json::to_string_pretty(&centrality)?)?;
+    
     println!("\nResults saved to {}", file_name);
+    println!("Centrality saved to {}", centrality_file);
     
     Ok(())
 }
@@ -651,16 +979,12 @@ async fn run_225a2_complete_trace(agent: &Agent) -> Result<(), Box<dyn std::erro
     }
     
     // Show balance distribution
-    let over_1m_icp = all_accounts_data.iter().filter(|(_, _, b, _, _, _, _, _)| *b > 100_000_000_000_000).count();
-    let over_100k_icp = all_accounts_data.iter().filter(|(_, _, b, _, _, _, _, _)| *b > 10_000_000_000_000).count();
-    let over_10k_icp = all_accounts_data.iter().filter(|(_, _, b, _, _, _, _, _)| *b > 1_000_000_000_000).count();
-    let over_1k_icp = all_accounts_data.iter().filter(|(_, _, b, _, _, _, _, _)| *b > 100_000_000_000).count();
+    let balances: Vec<u64> = all_accounts_data.iter().map(|(_, _, b, _, _, _, _, _)| *b).collect();
     
     println!("\nBalance Distribution:");
-    println!("  > 1M ICP: {} accounts", over_1m_icp);
-    println!("  > 100K ICP: {} accounts", over_100k_icp);
-    println!("  > 10K ICP: {} accounts", over_10k_icp);
-    println!("  > 1K ICP: {} accounts", over_1k_icp);
+    for bucket in balance_buckets(&balances) {
+        println!("  {}: {} accounts", bucket.label, bucket.accounts);
+    }
     
     println!("\nDetailed report saved to: {}", file_name);
     println!("This report includes balance history over time for each account.");
@@ -670,21 +994,7 @@ async fn run_225a2_complete_trace(agent: &Agent) -> Result<(), Box<dyn std::erro
 
 // get_entries
 fn get_entries() -> Vec<AccountData> {
-    let mut entries = Vec::new();
-
-    // single
-    entries.extend(DEFI.iter().map(|(name, addr)| AccountData::new(name, &[addr], Type::Defi)));
-    entries.extend(SNSES.iter().map(|(name, addr)| AccountData::new(name, &[addr], Type::Sns)));
-
-    // unnamed
-    entries.extend(SPAMMERS.iter().map(|addr| AccountData::new(&addr[..5], &[addr], Type::Spammer)));
-
-    // multiple
-    entries.extend(CEXES.iter().map(|(name, addrs)| AccountData::new(name, addrs, Type::Cex)));
-    entries.extend(FOUNDATION.iter().map(|(name, addrs)| AccountData::new(name, addrs, Type::Foundation)));
-    entries.extend(IDENTIFIED.iter().map(|(name, addrs)| AccountData::new(name, addrs, Type::Identified)));
-    entries.extend(NODE_PROVIDERS.iter().map(|(name, addrs)| AccountData::new(name, addrs, Type::NodeProvider)));
-    entries.extend(SUSPECTS.iter().map(|(name, addrs)| AccountData::new(name, addrs, Type::Suspect)));
+    let entries = entities::all_entries();
 
     validate_entries(&entries);
 
@@ -0,0 +1,362 @@
+// Time-respecting maximum flow between two account sets
+// Transfers become capacities in a time-expanded network: each account has one node per transfer it
+// takes part in, chained in block order, so value can only move forward in time
+
+use crate::{
+    entities::label_map,
+    ledger_db::LedgerDatabase,
+    transfer_graph::{GraphFilter, TransferEdge, TransferGraph},
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+const INFINITE: u64 = u64::MAX / 4;
+const SOURCE: usize = 0;
+const SINK: usize = 1;
+
+/// One unit of the flow decomposition: an amount moved along a chain of transfers
+#[derive(Debug, Serialize)]
+pub struct FlowPath {
+    pub amount: u64,
+    pub accounts: Vec<String>,
+    pub transfers: Vec<TransferEdge>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MaxFlowReport {
+    pub sources: usize,
+    pub sinks: usize,
+    pub min_amount: u64,
+    pub window_start: Option<u64>,
+    pub window_end: Option<u64>,
+    pub transfers_considered: usize,
+    pub max_flow_e8s: u64,
+    pub flow_by_sink: BTreeMap<String, u64>,
+    pub paths: Vec<FlowPath>,
+}
+
+/// Residual network solved with Dinic's algorithm
+///
+/// Edges are stored in pairs, so `e ^ 1` is the reverse of `e`.
+struct FlowNetwork {
+    adjacency: Vec<Vec<usize>>,
+    head: Vec<usize>,
+    residual: Vec<u64>,
+    capacity: Vec<u64>,
+    transfer: Vec<Option<usize>>,
+}
+
+impl FlowNetwork {
+    fn new(nodes: usize) -> Self {
+        Self {
+            adjacency: vec![Vec::new(); nodes],
+            head: Vec::new(),
+            residual: Vec::new(),
+            capacity: Vec::new(),
+            transfer: Vec::new(),
+        }
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize, capacity: u64, transfer: Option<usize>) {
+        for (tail, head, cap) in [(from, to, capacity), (to, from, 0)] {
+            self.adjacency[tail].push(self.head.len());
+            self.head.push(head);
+            self.residual.push(cap);
+            self.capacity.push(cap);
+            self.transfer.push(transfer);
+        }
+    }
+
+    fn levels(&self) -> Option<Vec<i64>> {
+        let mut level = vec![-1; self.adjacency.len()];
+        level[SOURCE] = 0;
+        let mut queue = VecDeque::from([SOURCE]);
+
+        while let Some(u) = queue.pop_front() {
+            for &e in &self.adjacency[u] {
+                let v = self.head[e];
+                if self.residual[e] > 0 && level[v] < 0 {
+                    level[v] = level[u] + 1;
+                    queue.push_back(v);
+                }
+            }
+        }
+
+        (level[SINK] >= 0).then_some(level)
+    }
+
+    /// Saturate a blocking flow; iterative because holdover chains can be very long
+    fn blocking_flow(&mut self, level: &mut [i64]) -> u64 {
+        let mut next_edge = vec![0usize; self.adjacency.len()];
+        let mut stack: Vec<usize> = Vec::new();
+        let mut total = 0u64;
+        let mut u = SOURCE;
+
+        loop {
+            if u == SINK {
+                let bottleneck = stack.iter().map(|e| self.residual[*e]).min().unwrap_or(0);
+                for &e in &stack {
+                    self.residual[e] -= bottleneck;
+                    self.residual[e ^ 1] += bottleneck;
+                }
+                total += bottleneck;
+
+                // Retreat to the tail of the first saturated edge
+                let saturated = stack.iter().position(|e| self.residual[*e] == 0).unwrap_or(0);
+                stack.truncate(saturated);
+                u = stack.last().map(|e| self.head[*e]).unwrap_or(SOURCE);
+                continue;
+            }
+
+            let advance = self.adjacency[u][next_edge[u]..]
+                .iter()
+                .position(|&e| self.residual[e] > 0 && level[self.head[e]] == level[u] + 1);
+
+            match advance {
+                Some(offset) => {
+                    next_edge[u] += offset;
+                    let e = self.adjacency[u][next_edge[u]];
+                    stack.push(e);
+                    u = self.head[e];
+                }
+                None => {
+                    level[u] = -1;
+                    let Some(e) = stack.pop() else { break };
+                    u = self.head[e ^ 1];
+                    next_edge[u] += 1;
+                }
+            }
+        }
+
+        total
+    }
+
+    fn max_flow(&mut self) -> u64 {
+        let mut total = 0;
+        while let Some(mut level) = self.levels() {
+            total += self.blocking_flow(&mut level);
+        }
+        total
+    }
+
+    /// Split the flow into source-to-sink paths; the network is acyclic so this terminates
+    fn decompose(&mut self) -> Vec<(u64, Vec<usize>)> {
+        let mut flow: Vec<u64> = (0..self.head.len())
+            .map(|e| if e.is_multiple_of(2) { self.capacity[e] - self.residual[e] } else { 0 })
+            .collect();
+        let mut paths = Vec::new();
+
+        loop {
+            let mut edges = Vec::new();
+            let mut u = SOURCE;
+            while u != SINK {
+                let Some(&e) = self.adjacency[u].iter().find(|&&e| flow[e] > 0) else { break };
+                edges.push(e);
+                u = self.head[e];
+            }
+            if u != SINK || edges.is_empty() {
+                break;
+            }
+
+            let amount = edges.iter().map(|e| flow[*e]).min().unwrap_or(0);
+            for &e in &edges {
+                flow[e] -= amount;
+            }
+            paths.push((amount, edges.iter().filter_map(|e| self.transfer[*e]).collect()));
+        }
+
+        paths
+    }
+}
+
+/// Keep only transfers that can lie on a time-respecting route from a source to a sink
+fn relevant_transfers(edges: &[TransferEdge], sources: &HashSet<String>, sinks: &HashSet<String>) -> Vec<usize> {
+    // Self-transfers move nothing between accounts
+    let usable: Vec<usize> = (0..edges.len())
+        .filter(|i| edges[*i].from != edges[*i].to)
+        .filter(|i| !sinks.contains(&edges[*i].from) && !sources.contains(&edges[*i].to))
+        .collect();
+
+    // Forward: the account must already hold source funds when it sends
+    let mut reached: HashSet<&str> = sources.iter().map(|s| s.as_str()).collect();
+    let mut forward = HashSet::new();
+    for &i in &usable {
+        let edge = &edges[i];
+        if reached.contains(edge.from.as_str()) {
+            reached.insert(edge.to.as_str());
+            forward.insert(i);
+        }
+    }
+
+    // Backward: the recipient must still be able to reach a sink afterwards
+    let mut reaches_sink: HashSet<&str> = sinks.iter().map(|s| s.as_str()).collect();
+    let mut kept = Vec::new();
+    for &i in usable.iter().rev() {
+        let edge = &edges[i];
+        if forward.contains(&i) && reaches_sink.contains(edge.to.as_str()) {
+            reaches_sink.insert(edge.from.as_str());
+            kept.push(i);
+        }
+    }
+    kept.reverse();
+
+    kept
+}
+
+/// Maximum flow from `sources` to `sinks` and its decomposition into paths
+///
+/// Capacities are the transfer amounts; funds may wait in an account for any length of time.
+pub fn time_respecting_max_flow(
+    graph: &TransferGraph,
+    sources: &HashSet<String>,
+    sinks: &HashSet<String>,
+) -> (u64, Vec<FlowPath>) {
+    let kept = relevant_transfers(&graph.edges, sources, sinks);
+
+    // One node per (account, transfer), assigned in time order
+    let mut nodes: HashMap<(&str, usize), usize> = HashMap::new();
+    let mut timeline: HashMap<&str, Vec<usize>> = HashMap::new();
+    let mut next_id = 2;
+    for &i in &kept {
+        let edge = &graph.edges[i];
+        for account in [edge.from.as_str(), edge.to.as_str()] {
+            if sinks.contains(account) {
+                continue;
+            }
+            nodes.insert((account, i), next_id);
+            timeline.entry(account).or_default().push(next_id);
+            next_id += 1;
+        }
+    }
+
+    let mut network = FlowNetwork::new(next_id);
+    for (account, chain) in &timeline {
+        if sources.contains(*account) {
+            network.add_edge(SOURCE, chain[0], INFINITE, None);
+        }
+        for pair in chain.windows(2) {
+            network.add_edge(pair[0], pair[1], INFINITE, None);
+        }
+    }
+    for &i in &kept {
+        let edge = &graph.edges[i];
+        let from = nodes[&(edge.from.as_str(), i)];
+        let to = if sinks.contains(&edge.to) { SINK } else { nodes[&(edge.to.as_str(), i)] };
+        network.add_edge(from, to, edge.amount, Some(i));
+    }
+
+    let total = network.max_flow();
+
+    let mut paths: Vec<FlowPath> = network
+        .decompose()
+        .into_iter()
+        .map(|(amount, transfers)| {
+            let transfers: Vec<TransferEdge> = transfers.iter().map(|i| graph.edges[*i].clone()).collect();
+            let accounts = transfers
+                .first()
+                .map(|t| t.from.clone())
+                .into_iter()
+                .chain(transfers.iter().map(|t| t.to.clone()))
+                .collect();
+            FlowPath { amount, accounts, transfers }
+        })
+        .collect();
+    paths.sort_by_key(|p| std::cmp::Reverse(p.amount));
+
+    (total, paths)
+}
+
+/// Run the max-flow analysis against ledger.db
+pub async fn run_max_flow(db_path: &str, sources: Vec<String>, sinks: Vec<String>, filter: GraphFilter) -> Result<()> {
+    println!("===== MAX FLOW =====");
+
+    let sources: HashSet<String> = sources.into_iter().collect();
+    let sinks: HashSet<String> = sinks.into_iter().filter(|a| !sources.contains(a)).collect();
+    println!("Sources: {}, sinks: {}", sources.len(), sinks.len());
+
+    let db = LedgerDatabase::new(db_path)?;
+    let graph = TransferGraph::load(&db, &filter)?;
+    println!("Loaded {} transfers", graph.edges.len());
+
+    let (max_flow, paths) = time_respecting_max_flow(&graph, &sources, &sinks);
+
+    let labels = label_map();
+    let mut flow_by_sink: BTreeMap<String, u64> = BTreeMap::new();
+    for path in &paths {
+        if let Some(sink) = path.accounts.last() {
+            let name = labels.get(sink).map(|(name, _)| name.clone()).unwrap_or_else(|| sink.clone());
+            *flow_by_sink.entry(name).or_insert(0) += path.amount;
+        }
+    }
+
+    println!("\nMax flow: {} ICP over {} paths", max_flow as f64 / 100_000_000.0, paths.len());
+    for (sink, amount) in &flow_by_sink {
+        println!("  {}: {} ICP", sink, *amount as f64 / 100_000_000.0);
+    }
+
+    let report = MaxFlowReport {
+        sources: sources.len(),
+        sinks: sinks.len(),
+        min_amount: filter.min_amount,
+        window_start: filter.window.start,
+        window_end: filter.window.end,
+        transfers_considered: graph.edges.len(),
+        max_flow_e8s: max_flow,
+        flow_by_sink,
+        paths,
+    };
+
+    let file_name = "./max_flow_report.json";
+    std::fs::write(file_name, serde_json::to_string_pretty(&report)?)?;
+
+    println!("\nMax flow report saved to: {}", file_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(block: u64, from: &str, to: &str, amount: u64) -> TransferEdge {
+        TransferEdge { block, from: from.to_string(), to: to.to_string(), amount, timestamp: block }
+    }
+
+    fn set(accounts: &[&str]) -> HashSet<String> {
+        accounts.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_max_flow_respects_time() {
+        let graph = TransferGraph::from_edges(vec![
+            edge(1, "b", "x", 50), // b has no source funds yet
+            edge(2, "s", "a", 100),
+            edge(3, "s", "b", 70),
+            edge(4, "a", "x", 60),
+            edge(5, "a", "c", 30),
+            edge(6, "c", "x", 40),
+            edge(7, "b", "x", 20),
+        ]);
+
+        let (total, paths) = time_respecting_max_flow(&graph, &set(&["s"]), &set(&["x"]));
+        assert_eq!(total, 110);
+        assert_eq!(paths.iter().map(|p| p.amount).sum::<u64>(), 110);
+        assert!(paths.iter().all(|p| p.transfers.windows(2).all(|w| w[0].block < w[1].block)));
+        assert!(paths.iter().all(|p| p.transfers.iter().all(|t| t.block != 1)));
+    }
+
+    #[test]
+    fn test_self_transfer_does_not_merge_accounts() {
+        let graph = TransferGraph::from_edges(vec![
+            edge(1, "s", "m", 100),
+            edge(2, "m", "m", 5),
+            edge(3, "s", "n", 1),
+            edge(4, "n", "t", 1),
+            edge(5, "m", "t", 1000),
+        ]);
+
+        let (total, _) = time_respecting_max_flow(&graph, &set(&["s"]), &set(&["t"]));
+        assert_eq!(total, 101);
+    }
+}
@@ -20,6 +20,12 @@ pub struct NetworkNode {
     pub is_seed: bool,
     pub depth: u32,
     pub patterns_detected: Vec<String>,
+    #[serde(default)]
+    pub community: Option<usize>,
+    #[serde(default)]
+    pub pagerank: Option<f64>,
+    #[serde(default)]
+    pub betweenness: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -209,6 +215,8 @@ impl NetworkTracer {
                 to: tx.to.clone(),
                 amount: tx.amount,
                 timestamp: tx.timestamp,
+                block: tx.id,
+                fee: tx.fee,
             }
         }).collect();
         
@@ -230,6 +238,9 @@ impl NetworkTracer {
             is_seed: self.seed_addresses.contains(address),
             depth,
             patterns_detected: pattern_names,
+            community: None,
+            pagerank: None,
+            betweenness: None,
         };
         
         (node, connected_addresses)
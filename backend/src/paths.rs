@@ -0,0 +1,241 @@
+// Time-respecting paths between two accounts over the transfer graph
+// Each hop must happen after the previous one, so a path is a route funds could actually have taken
+
+use crate::{
+    entities::label_map,
+    ledger_db::LedgerDatabase,
+    transfer_graph::{GraphFilter, TransferEdge, TransferGraph},
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+#[derive(Debug, Serialize)]
+pub struct PathHop {
+    pub from: String,
+    pub to: String,
+    pub transaction: TransferEdge,
+    /// Every transfer on this hop between the previous and the next hop
+    pub supporting_transactions: Vec<TransferEdge>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransferPath {
+    pub hops: Vec<PathHop>,
+    pub start_timestamp: u64,
+    pub end_timestamp: u64,
+    /// Smallest transfer along the path
+    pub bottleneck_amount: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PathReport {
+    pub from: String,
+    pub from_label: Option<String>,
+    pub to: String,
+    pub to_label: Option<String>,
+    pub max_hops: usize,
+    pub min_amount: u64,
+    pub window_start: Option<u64>,
+    pub window_end: Option<u64>,
+    pub paths: Vec<TransferPath>,
+}
+
+/// Up to `k` simple time-respecting paths, fewest hops first, then earliest arrival
+///
+/// Returns edge indices into `graph.edges`. A partial path is dropped once `k` paths reaching the same
+/// account with no more hops and no later arrival have been expanded, since those can continue everywhere
+/// it can; only the earliest usable transfer to each neighbour is followed.
+pub fn k_shortest_paths(graph: &TransferGraph, from: &str, to: &str, max_hops: usize, k: usize) -> Vec<Vec<usize>> {
+    // (hops, arrival time, arrival block, path)
+    let mut heap = BinaryHeap::new();
+    heap.push(Reverse((0usize, 0u64, 0u64, Vec::<usize>::new())));
+    // (hops, arrival time) of the partial paths expanded at each account
+    let mut expanded: HashMap<&str, Vec<(usize, u64)>> = HashMap::new();
+    let mut results = Vec::new();
+
+    while let Some(Reverse((hops, arrival, arrival_block, path))) = heap.pop() {
+        let node = path.last().map(|i| graph.edges[*i].to.as_str()).unwrap_or(from);
+
+        if node == to && !path.is_empty() {
+            results.push(path);
+            if results.len() >= k {
+                break;
+            }
+            continue;
+        }
+        if hops >= max_hops {
+            continue;
+        }
+
+        let labels = expanded.entry(node).or_default();
+        if labels.iter().filter(|(h, t)| *h <= hops && *t <= arrival).count() >= k {
+            continue;
+        }
+        labels.push((hops, arrival));
+
+        let visited: HashSet<&str> =
+            std::iter::once(from).chain(path.iter().map(|i| graph.edges[*i].to.as_str())).collect();
+        let mut seen_neighbours = HashSet::new();
+
+        for &i in graph.outgoing_indices(node) {
+            let edge = &graph.edges[i];
+            if !path.is_empty() && (edge.timestamp, edge.block) <= (arrival, arrival_block) {
+                continue;
+            }
+            if visited.contains(edge.to.as_str()) || !seen_neighbours.insert(edge.to.as_str()) {
+                continue;
+            }
+
+            let mut next = path.clone();
+            next.push(i);
+            heap.push(Reverse((hops + 1, edge.timestamp, edge.block, next)));
+        }
+    }
+
+    results
+}
+
+/// Expand edge indices into hops with their supporting transactions
+pub fn build_path(graph: &TransferGraph, edge_indices: &[usize]) -> TransferPath {
+    let path_edges: Vec<&TransferEdge> = edge_indices.iter().map(|i| &graph.edges[*i]).collect();
+
+    let hops = path_edges
+        .iter()
+        .enumerate()
+        .map(|(i, edge)| {
+            let after = i.checked_sub(1).map(|p| path_edges[p].timestamp);
+            let before = path_edges.get(i + 1).map(|n| n.timestamp);
+            let supporting_transactions = graph
+                .outgoing(&edge.from)
+                .filter(|e| e.to == edge.to)
+                .filter(|e| after.is_none_or(|a| e.timestamp >= a) && before.is_none_or(|b| e.timestamp <= b))
+                .cloned()
+                .collect();
+
+            PathHop {
+                from: edge.from.clone(),
+                to: edge.to.clone(),
+                transaction: (*edge).clone(),
+                supporting_transactions,
+            }
+        })
+        .collect();
+
+    TransferPath {
+        hops,
+        start_timestamp: path_edges.first().map(|e| e.timestamp).unwrap_or(0),
+        end_timestamp: path_edges.last().map(|e| e.timestamp).unwrap_or(0),
+        bottleneck_amount: path_edges.iter().map(|e| e.amount).min().unwrap_or(0),
+    }
+}
+
+/// Run the path search against ledger.db
+pub async fn run_find_paths(
+    db_path: &str,
+    from: &str,
+    to: &str,
+    filter: GraphFilter,
+    max_hops: usize,
+    k: usize,
+) -> Result<()> {
+    println!("===== FIND PATHS =====");
+    println!("From: {}", from);
+    println!("To: {}", to);
+    println!("Max hops: {}, min amount: {} ICP", max_hops, filter.min_amount as f64 / 100_000_000.0);
+
+    let db = LedgerDatabase::new(db_path)?;
+    let graph = TransferGraph::load(&db, &filter)?;
+    println!("Loaded {} transfers", graph.edges.len());
+
+    let paths: Vec<TransferPath> =
+        k_shortest_paths(&graph, from, to, max_hops, k).iter().map(|p| build_path(&graph, p)).collect();
+
+    let labels = label_map();
+    let label = |account: &str| labels.get(account).map(|(name, ty)| format!("{} ({})", name, ty));
+
+    println!("\nFound {} path(s)", paths.len());
+    for (i, path) in paths.iter().enumerate() {
+        println!(
+            "\nPath {} ({} hops, bottleneck {} ICP):",
+            i + 1,
+            path.hops.len(),
+            path.bottleneck_amount as f64 / 100_000_000.0
+        );
+        for hop in &path.hops {
+            println!(
+                "  {} -> {} {} ICP at block {} ({} supporting)",
+                &hop.from[..8],
+                &hop.to[..8],
+                hop.transaction.amount as f64 / 100_000_000.0,
+                hop.transaction.block,
+                hop.supporting_transactions.len()
+            );
+        }
+    }
+
+    let report = PathReport {
+        from: from.to_string(),
+        from_label: label(from),
+        to: to.to_string(),
+        to_label: label(to),
+        max_hops,
+        min_amount: filter.min_amount,
+        window_start: filter.window.start,
+        window_end: filter.window.end,
+        paths,
+    };
+
+    let file_name = format!("./paths_{}_{}.json", &from[..8.min(from.len())], &to[..8.min(to.len())]);
+    std::fs::write(&file_name, serde_json::to_string_pretty(&report)?)?;
+
+    println!("\nPaths saved to: {}", file_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(block: u64, from: &str, to: &str, timestamp: u64) -> TransferEdge {
+        TransferEdge { block, from: from.to_string(), to: to.to_string(), amount: 100, timestamp }
+    }
+
+    #[test]
+    fn test_paths_respect_time_order() {
+        let graph = TransferGraph::from_edges(vec![
+            edge(1, "a", "b", 10),
+            edge(2, "b", "d", 5), // before a -> b, unusable
+            edge(3, "b", "d", 20),
+            edge(4, "a", "c", 11),
+            edge(5, "c", "d", 30),
+            edge(6, "a", "d", 40),
+        ]);
+
+        let paths = k_shortest_paths(&graph, "a", "d", 3, 3);
+        let blocks: Vec<Vec<u64>> = paths.iter().map(|p| p.iter().map(|i| graph.edges[*i].block).collect()).collect();
+        assert_eq!(blocks, vec![vec![6], vec![1, 3], vec![4, 5]]);
+
+        let path = build_path(&graph, &paths[1]);
+        assert_eq!(path.hops[1].supporting_transactions.len(), 1);
+        assert!(k_shortest_paths(&graph, "d", "a", 3, 3).is_empty());
+    }
+
+    #[test]
+    fn test_late_short_path_does_not_block_earlier_route() {
+        let graph = TransferGraph::from_edges(vec![
+            edge(1, "a", "y", 1),
+            edge(2, "y", "x", 2),
+            edge(3, "x", "d", 10),
+            edge(4, "a", "x", 50), // reaches x in one hop, but too late to continue
+        ]);
+
+        let paths = k_shortest_paths(&graph, "a", "d", 3, 1);
+        let blocks: Vec<Vec<u64>> = paths.iter().map(|p| p.iter().map(|i| graph.edges[*i].block).collect()).collect();
+        assert_eq!(blocks, vec![vec![1, 2, 3]]);
+    }
+}
@@ -1,9 +1,12 @@
-use crate::addresses::CEXES;
+use crate::{addresses::CEXES, ledger_db::NANOS_PER_DAY};
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
 const SIX_WEEKS_NANOS: u64 = 6 * 7 * 24 * 60 * 60 * 1_000_000_000; // 6 weeks in nanoseconds
 const TOLERANCE_NANOS: u64 = 7 * 24 * 60 * 60 * 1_000_000_000; // 1 week tolerance
+const MIN_HOLDING_NANOS: u64 = 30 * 24 * 60 * 60 * 1_000_000_000; // 30 days untouched
+const LARGE_AMOUNT: u64 = 10_000 * 100_000_000; // 10,000 ICP (1 ICP = 100_000_000 e8s)
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SuspiciousPattern {
@@ -13,6 +16,10 @@ pub struct SuspiciousPattern {
     pub deposits: Vec<ExchangeTransfer>,
     pub total_amount: u64,
     pub holding_periods: Vec<HoldingPeriod>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub peels: Vec<Peel>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transfers: Vec<Transaction>, // Every transfer the pattern was built from
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +27,18 @@ pub enum PatternType {
     ExchangeCycle, // Withdraw from exchange -> Hold -> Deposit to exchange
     LargeHolding,  // Large amounts held for specific periods
     MixerPattern,  // Multiple small transactions to obfuscate origin
+    PeelChain,     // Long chain forwarding most of the balance and peeling small amounts off
+    Custom(String), // Produced by a detector registered outside this module
+}
+
+impl PatternType {
+    /// Short name used when storing findings
+    pub fn name(&self) -> String {
+        match self {
+            PatternType::Custom(name) => name.clone(),
+            other => format!("{:?}", other),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +48,8 @@ pub struct ExchangeTransfer {
     pub amount: u64,
     pub timestamp: u64,
     pub is_withdrawal: bool,
+    #[serde(default)]
+    pub block: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,6 +58,20 @@ pub struct HoldingPeriod {
     pub end_timestamp: u64,
     pub duration_days: f64,
     pub amount_held: u64,
+    #[serde(default)]
+    pub start_block: u64, // Ledger block that opened the period
+    #[serde(default)]
+    pub end_block: u64,   // Ledger block that closed it
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Peel {
+    pub hop: usize,              // Position in the chain, 0 is the start account
+    pub account: String,         // Account that peeled the amount off
+    pub destination: String,
+    pub amount: u64,
+    pub timestamp: u64,
+    pub block: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,37 +80,164 @@ pub struct Transaction {
     pub to: String,
     pub amount: u64,
     pub timestamp: u64,
+    #[serde(default)]
+    pub block: u64, // Ledger block index
+    #[serde(default)]
+    pub fee: u64,   // Paid by the sender on top of the amount
 }
 
-pub struct PatternDetector {
-    exchange_addresses: HashMap<String, String>, // address -> exchange name
+/// A tunable detector parameter, shown by `detectors` and set with `--params name.param=value`
+#[derive(Debug, Clone, Serialize)]
+pub struct DetectorParam {
+    pub name: &'static str,
+    pub value: String,
+    pub description: &'static str,
 }
 
-impl PatternDetector {
-    pub fn new() -> Self {
-        let mut exchange_addresses = HashMap::new();
-        
-        // Build lookup map for exchange addresses
-        for (exchange_name, addresses) in CEXES {
-            for address in *addresses {
-                exchange_addresses.insert(address.to_string(), exchange_name.to_string());
-            }
+/// A `SuspiciousPattern` field a detector fills in, and what it holds
+#[derive(Debug, Clone, Serialize)]
+pub struct EvidenceField {
+    pub field: &'static str,
+    pub description: &'static str,
+}
+
+/// A single pattern detector working on one account's transactions
+pub trait Detector: Send + Sync {
+    fn name(&self) -> &'static str;
+    
+    fn description(&self) -> &'static str;
+    
+    /// Bumped whenever the detector's logic changes, and stored with each finding
+    fn version(&self) -> &'static str {
+        "1"
+    }
+    
+    fn params(&self) -> Vec<DetectorParam>;
+    
+    fn set_param(&mut self, name: &str, value: &str) -> Result<()>;
+    
+    /// The fields of the emitted patterns that carry evidence
+    fn evidence_schema(&self) -> Vec<EvidenceField>;
+    
+    fn detect(&self, account: &str, transactions: &[Transaction]) -> Vec<SuspiciousPattern>;
+}
+
+fn parse_param<T: std::str::FromStr>(detector: &str, name: &str, value: &str) -> Result<T> {
+    value.parse().map_err(|_| anyhow!("invalid value '{}' for {}.{}", value, detector, name))
+}
+
+fn unknown_param(detector: &str, name: &str) -> anyhow::Error {
+    anyhow!("detector '{}' has no parameter '{}'", detector, name)
+}
+
+fn exchange_addresses() -> HashMap<String, String> {
+    let mut exchange_addresses = HashMap::new();
+    
+    // Build lookup map for exchange addresses
+    for (exchange_name, addresses) in CEXES {
+        for address in *addresses {
+            exchange_addresses.insert(address.to_string(), exchange_name.to_string());
         }
-        
-        Self { exchange_addresses }
     }
     
-    pub fn detect_patterns(&self, account: &str, transactions: &[Transaction]) -> Vec<SuspiciousPattern> {
-        let mut patterns = Vec::new();
-        
-        // Detect exchange cycle pattern
-        if let Some(pattern) = self.detect_exchange_cycle(account, transactions) {
-            patterns.push(pattern);
+    exchange_addresses
+}
+
+//
+// Exchange cycle
+//
+
+/// Matching rules for exchange cycles
+#[derive(Debug, Clone)]
+pub struct ExchangeCycleConfig {
+    pub holding_nanos: u64,     // Expected time between withdrawal and deposit
+    pub tolerance_nanos: u64,   // Allowed deviation from the expected holding time
+    pub amount_tolerance: f64,  // Relative difference allowed between a withdrawal and its deposits
+    pub max_split: usize,       // Most deposits a single withdrawal can be matched with
+    pub cross_exchange: bool,   // Whether a withdrawal may come back to a different exchange
+}
+
+impl Default for ExchangeCycleConfig {
+    fn default() -> Self {
+        Self {
+            holding_nanos: SIX_WEEKS_NANOS,
+            tolerance_nanos: TOLERANCE_NANOS,
+            amount_tolerance: 0.1,
+            max_split: 3,
+            cross_exchange: true,
+        }
+    }
+}
+
+const MAX_SPLIT_CANDIDATES: usize = 12; // Deposits considered per withdrawal when looking for splits
+const MAX_SEARCH_NODES: usize = 200_000; // Bound on the matching search; the best match found so far is kept
+
+/// One way to match a withdrawal: the deposits it went back out as
+struct CycleMatch {
+    deposits: Vec<usize>,
+    held: u64,
+    deviation: u64,
+}
+
+/// Branch-and-bound search for the set of disjoint matches holding the most ICP
+struct MatchSearch<'a> {
+    candidates: &'a [Vec<CycleMatch>],
+    bounds: Vec<u64>, // Most ICP the withdrawals from index i onwards could hold
+    used: Vec<bool>,
+    current: Vec<Option<usize>>,
+    best: Vec<Option<usize>>,
+    best_score: (u64, std::cmp::Reverse<u64>),
+    nodes: usize,
+}
+
+impl MatchSearch<'_> {
+    fn search(&mut self, i: usize, held: u64, deviation: u64) {
+        self.nodes += 1;
+        if i == self.candidates.len() {
+            let score = (held, std::cmp::Reverse(deviation));
+            if score > self.best_score {
+                self.best_score = score;
+                self.best = self.current.clone();
+            }
+            return;
+        }
+        if held + self.bounds[i] < self.best_score.0 || self.nodes > MAX_SEARCH_NODES {
+            return;
         }
         
-        // Add more pattern detection methods here
+        for (c, candidate) in self.candidates[i].iter().enumerate() {
+            if candidate.deposits.iter().any(|d| self.used[*d]) {
+                continue;
+            }
+            for d in &candidate.deposits {
+                self.used[*d] = true;
+            }
+            self.current[i] = Some(c);
+            self.search(i + 1, held + candidate.held, deviation + candidate.deviation);
+            self.current[i] = None;
+            for d in &candidate.deposits {
+                self.used[*d] = false;
+            }
+        }
         
-        patterns
+        // Leave this withdrawal unmatched
+        self.search(i + 1, held, deviation);
+    }
+}
+
+/// Withdraw from an exchange, hold for about six weeks, deposit back
+pub struct ExchangeCycleDetector {
+    exchange_addresses: HashMap<String, String>, // address -> exchange name
+    config: ExchangeCycleConfig,
+}
+
+impl ExchangeCycleDetector {
+    pub fn new() -> Self {
+        Self { exchange_addresses: exchange_addresses(), config: ExchangeCycleConfig::default() }
+    }
+    
+    pub fn with_config(config: ExchangeCycleConfig) -> Self {
+        Self { exchange_addresses: exchange_addresses(), config }
     }
     
     fn detect_exchange_cycle(&self, account: &str, transactions: &[Transaction]) -> Option<SuspiciousPattern> {
@@ -92,6 +254,7 @@ impl PatternDetector {
                     amount: tx.amount,
                     timestamp: tx.timestamp,
                     is_withdrawal: true,
+                    block: tx.block,
                 });
             }
             
@@ -103,6 +266,7 @@ impl PatternDetector {
                     amount: tx.amount,
                     timestamp: tx.timestamp,
                     is_withdrawal: false,
+                    block: tx.block,
                 });
             }
         }
@@ -111,32 +275,26 @@ impl PatternDetector {
         withdrawals.sort_by_key(|w| w.timestamp);
         deposits.sort_by_key(|d| d.timestamp);
         
-        // Find matching withdrawal-deposit pairs with ~6 week holding period
+        // Match every withdrawal with the deposits it came back as
         let mut holding_periods = Vec::new();
-        let mut matched_deposits = HashSet::new();
-        
-        for withdrawal in &withdrawals {
-            for (idx, deposit) in deposits.iter().enumerate() {
-                if matched_deposits.contains(&idx) {
-                    continue;
-                }
-                
-                let time_diff = deposit.timestamp.saturating_sub(withdrawal.timestamp);
+        for (withdrawal, matched) in self.match_cycles(&withdrawals, &deposits) {
+            let withdrawal = &withdrawals[withdrawal];
+            let mut remaining = withdrawal.amount;
+            
+            // A split withdrawal holds each part until its own deposit
+            for deposit in matched.iter().map(|d| &deposits[*d]) {
+                let time_diff = deposit.timestamp - withdrawal.timestamp;
+                let amount_held = deposit.amount.min(remaining);
+                remaining -= amount_held;
                 
-                // Check if holding period is around 6 weeks (with tolerance)
-                if time_diff >= (SIX_WEEKS_NANOS - TOLERANCE_NANOS) 
-                    && time_diff <= (SIX_WEEKS_NANOS + TOLERANCE_NANOS) {
-                    
-                    holding_periods.push(HoldingPeriod {
-                        start_timestamp: withdrawal.timestamp,
-                        end_timestamp: deposit.timestamp,
-                        duration_days: time_diff as f64 / (24.0 * 60.0 * 60.0 * 1_000_000_000.0),
-                        amount_held: withdrawal.amount.min(deposit.amount),
-                    });
-                    
-                    matched_deposits.insert(idx);
-                    break;
-                }
+                holding_periods.push(HoldingPeriod {
+                    start_timestamp: withdrawal.timestamp,
+                    end_timestamp: deposit.timestamp,
+                    duration_days: time_diff as f64 / NANOS_PER_DAY as f64,
+                    amount_held,
+                    start_block: withdrawal.block,
+                    end_block: deposit.block,
+                });
             }
         }
         
@@ -151,15 +309,619 @@ impl PatternDetector {
                 deposits,
                 total_amount,
                 holding_periods,
+                peels: Vec::new(),
+                transfers: Vec::new(),
             })
         } else {
             None
         }
     }
     
-    pub fn is_large_amount(&self, amount: u64) -> bool {
-        // Consider amounts over 10,000 ICP as large (1 ICP = 100_000_000 e8s)
-        amount > 10_000 * 100_000_000
+    /// Every way each withdrawal could be matched: one deposit, or a split over several,
+    /// inside the holding window and adding up to about the withdrawn amount
+    fn match_candidates(&self, withdrawal: &ExchangeTransfer, deposits: &[ExchangeTransfer]) -> Vec<CycleMatch> {
+        // Saturating, as the windows come from user-supplied day counts
+        let earliest =
+            withdrawal.timestamp.saturating_add(self.config.holding_nanos.saturating_sub(self.config.tolerance_nanos));
+        let latest =
+            withdrawal.timestamp.saturating_add(self.config.holding_nanos).saturating_add(self.config.tolerance_nanos);
+        let target = withdrawal.timestamp.saturating_add(self.config.holding_nanos);
+        
+        let mut eligible: Vec<usize> = (0..deposits.len())
+            .filter(|d| (earliest..=latest).contains(&deposits[*d].timestamp))
+            .filter(|d| self.config.cross_exchange || deposits[*d].exchange_name == withdrawal.exchange_name)
+            .collect();
+        eligible.sort_by_key(|d| deposits[*d].timestamp.abs_diff(target));
+        eligible.truncate(MAX_SPLIT_CANDIDATES);
+        eligible.sort_unstable();
+        
+        let tolerance = (withdrawal.amount as f64 * self.config.amount_tolerance) as u64;
+        let mut candidates = Vec::new();
+        let mut chosen = Vec::new();
+        
+        fn combine(
+            eligible: &[usize],
+            deposits: &[ExchangeTransfer],
+            max_split: usize,
+            sum: u64,
+            chosen: &mut Vec<usize>,
+            found: &mut dyn FnMut(&[usize], u64),
+        ) {
+            for (i, d) in eligible.iter().enumerate() {
+                chosen.push(*d);
+                let total = sum + deposits[*d].amount;
+                found(chosen, total);
+                if chosen.len() < max_split {
+                    combine(&eligible[i + 1..], deposits, max_split, total, chosen, found);
+                }
+                chosen.pop();
+            }
+        }
+        
+        combine(&eligible, deposits, self.config.max_split.max(1), 0, &mut chosen, &mut |set, total| {
+            let deviation = total.abs_diff(withdrawal.amount);
+            if deviation <= tolerance {
+                candidates.push(CycleMatch { deposits: set.to_vec(), held: total.min(withdrawal.amount), deviation });
+            }
+        });
+        
+        candidates.sort_by_key(|c| (std::cmp::Reverse(c.held), c.deviation));
+        candidates
+    }
+    
+    /// Pick disjoint matches holding the most ICP overall (ties go to the closest amounts),
+    /// rather than taking the first deposit that fits each withdrawal
+    fn match_cycles(&self, withdrawals: &[ExchangeTransfer], deposits: &[ExchangeTransfer]) -> Vec<(usize, Vec<usize>)> {
+        let candidates: Vec<Vec<CycleMatch>> = withdrawals.iter().map(|w| self.match_candidates(w, deposits)).collect();
+        
+        let mut bounds = vec![0; candidates.len() + 1];
+        for i in (0..candidates.len()).rev() {
+            bounds[i] = bounds[i + 1] + candidates[i].first().map(|c| c.held).unwrap_or(0);
+        }
+        
+        let mut search = MatchSearch {
+            candidates: &candidates,
+            bounds,
+            used: vec![false; deposits.len()],
+            current: vec![None; candidates.len()],
+            best: vec![None; candidates.len()],
+            best_score: (0, std::cmp::Reverse(0)),
+            nodes: 0,
+        };
+        search.search(0, 0, 0);
+        
+        search
+            .best
+            .iter()
+            .enumerate()
+            .filter_map(|(w, c)| c.map(|c| (w, candidates[w][c].deposits.clone())))
+            .collect()
+    }
+}
+
+impl Default for ExchangeCycleDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Detector for ExchangeCycleDetector {
+    fn name(&self) -> &'static str {
+        "exchange_cycle"
+    }
+    
+    fn description(&self) -> &'static str {
+        "Exchange withdrawal held for a set period, then deposited back to an exchange in one or more parts"
+    }
+    
+    fn params(&self) -> Vec<DetectorParam> {
+        vec![
+            DetectorParam {
+                name: "holding_days",
+                value: (self.config.holding_nanos as f64 / NANOS_PER_DAY as f64).to_string(),
+                description: "expected time between withdrawal and deposit",
+            },
+            DetectorParam {
+                name: "tolerance_days",
+                value: (self.config.tolerance_nanos as f64 / NANOS_PER_DAY as f64).to_string(),
+                description: "allowed deviation from the expected holding time",
+            },
+            DetectorParam {
+                name: "amount_tolerance",
+                value: self.config.amount_tolerance.to_string(),
+                description: "relative difference allowed between a withdrawal and its deposits",
+            },
+            DetectorParam {
+                name: "max_split",
+                value: self.config.max_split.to_string(),
+                description: "most deposits a single withdrawal can be matched with",
+            },
+            DetectorParam {
+                name: "cross_exchange",
+                value: self.config.cross_exchange.to_string(),
+                description: "whether a withdrawal may be deposited to a different exchange",
+            },
+        ]
+    }
+    
+    fn set_param(&mut self, name: &str, value: &str) -> Result<()> {
+        let detector = self.name();
+        match name {
+            "holding_days" => self.config.holding_nanos = (parse_param::<f64>(detector, name, value)? * NANOS_PER_DAY as f64) as u64,
+            "tolerance_days" => {
+                self.config.tolerance_nanos = (parse_param::<f64>(detector, name, value)? * NANOS_PER_DAY as f64) as u64
+            }
+            "amount_tolerance" => self.config.amount_tolerance = parse_param(detector, name, value)?,
+            "max_split" => self.config.max_split = parse_param(detector, name, value)?,
+            "cross_exchange" => self.config.cross_exchange = parse_param(detector, name, value)?,
+            _ => return Err(unknown_param(detector, name)),
+        }
+        Ok(())
+    }
+    
+    fn evidence_schema(&self) -> Vec<EvidenceField> {
+        vec![
+            EvidenceField { field: "withdrawals", description: "every withdrawal from an exchange" },
+            EvidenceField { field: "deposits", description: "every deposit to an exchange" },
+            EvidenceField { field: "holding_periods", description: "one per matched deposit; split withdrawals have several" },
+        ]
+    }
+    
+    fn detect(&self, account: &str, transactions: &[Transaction]) -> Vec<SuspiciousPattern> {
+        self.detect_exchange_cycle(account, transactions).into_iter().collect()
+    }
+}
+
+//
+// Large holding
+//
+
+/// Large receipts left untouched for a long time before being moved
+pub struct LargeHoldingDetector {
+    min_amount: u64,
+    min_holding_nanos: u64,
+}
+
+impl LargeHoldingDetector {
+    pub fn new() -> Self {
+        Self { min_amount: LARGE_AMOUNT, min_holding_nanos: MIN_HOLDING_NANOS }
+    }
+    
+    fn detect_large_holding(&self, account: &str, transactions: &[Transaction]) -> Option<SuspiciousPattern> {
+        let mut sorted: Vec<&Transaction> = transactions.iter().collect();
+        sorted.sort_by_key(|tx| tx.timestamp);
+        
+        // Replay the account's balance, fees included; a holding period opens once the balance
+        // is above the threshold and lasts until the next transfer out of the account
+        let mut balance: u64 = 0;
+        let mut open: Option<&Transaction> = None; // Transaction the period started at
+        let mut holding_periods = Vec::new();
+        let mut evidence = Vec::new();
+        
+        for tx in sorted {
+            let outgoing = tx.from == account;
+            let incoming = tx.to == account;
+            if outgoing && !incoming {
+                if let Some(start) = open.take() {
+                    let duration = tx.timestamp.saturating_sub(start.timestamp);
+                    if duration >= self.min_holding_nanos {
+                        holding_periods.push(HoldingPeriod {
+                            start_timestamp: start.timestamp,
+                            end_timestamp: tx.timestamp,
+                            duration_days: duration as f64 / NANOS_PER_DAY as f64,
+                            amount_held: balance,
+                            start_block: start.block,
+                            end_block: tx.block,
+                        });
+                        evidence.push(start.clone());
+                        evidence.push(tx.clone());
+                    }
+                }
+                balance = balance.saturating_sub(tx.amount + tx.fee);
+            } else if incoming && !outgoing {
+                balance += tx.amount;
+            } else if outgoing {
+                balance = balance.saturating_sub(tx.fee);
+            }
+            
+            // Whatever is left after an outflow is untouched from that point on
+            if open.is_none() && balance > self.min_amount {
+                open = Some(tx);
+            }
+        }
+        
+        if holding_periods.is_empty() {
+            return None;
+        }
+        
+        Some(SuspiciousPattern {
+            account: account.to_string(),
+            pattern_type: PatternType::LargeHolding,
+            withdrawals: Vec::new(),
+            deposits: Vec::new(),
+            total_amount: holding_periods.iter().map(|hp| hp.amount_held).max().unwrap_or(0),
+            holding_periods,
+            peels: Vec::new(),
+            transfers: evidence,
+        })
+    }
+}
+
+impl Default for LargeHoldingDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Detector for LargeHoldingDetector {
+    fn name(&self) -> &'static str {
+        "large_holding"
+    }
+    
+    fn description(&self) -> &'static str {
+        "Large receipt held untouched for a long period before being moved"
+    }
+    
+    fn params(&self) -> Vec<DetectorParam> {
+        vec![
+            DetectorParam {
+                name: "min_amount",
+                value: self.min_amount.to_string(),
+                description: "receipts above this many e8s open a holding period",
+            },
+            DetectorParam {
+                name: "min_days",
+                value: (self.min_holding_nanos as f64 / NANOS_PER_DAY as f64).to_string(),
+                description: "shortest holding period reported",
+            },
+        ]
+    }
+    
+    fn set_param(&mut self, name: &str, value: &str) -> Result<()> {
+        match name {
+            "min_amount" => self.min_amount = parse_param(self.name(), name, value)?,
+            "min_days" => self.min_holding_nanos = (parse_param::<f64>(self.name(), name, value)? * NANOS_PER_DAY as f64) as u64,
+            _ => return Err(unknown_param(self.name(), name)),
+        }
+        Ok(())
+    }
+    
+    fn evidence_schema(&self) -> Vec<EvidenceField> {
+        vec![
+            EvidenceField { field: "holding_periods", description: "balance held untouched from receipt until it moved" },
+            EvidenceField { field: "transfers", description: "the receipt and the transfer that moved it, per period" },
+        ]
+    }
+    
+    fn detect(&self, account: &str, transactions: &[Transaction]) -> Vec<SuspiciousPattern> {
+        self.detect_large_holding(account, transactions).into_iter().collect()
+    }
+}
+
+//
+// Mixer
+//
+
+/// Thresholds for fan-out/fan-in structuring
+#[derive(Debug, Clone)]
+pub struct MixerConfig {
+    pub min_fan_width: usize,        // Minimum number of similar transfers in a fan
+    pub amount_similarity: f64,      // Relative difference allowed between transfers of one fan
+    pub fan_window_nanos: u64,       // Time span of a single fan
+    pub converge_window_nanos: u64,  // How long after a fan the funds may reconverge
+    pub min_consolidated_share: f64, // Share of a fan-in that must leave in one transfer
+}
+
+impl Default for MixerConfig {
+    fn default() -> Self {
+        Self {
+            min_fan_width: 5,
+            amount_similarity: 0.1,
+            fan_window_nanos: 24 * 60 * 60 * 1_000_000_000, // 1 day
+            converge_window_nanos: 4 * 7 * 24 * 60 * 60 * 1_000_000_000, // 4 weeks
+            min_consolidated_share: 0.8,
+        }
+    }
+}
+
+/// Fan-out/fan-in structuring
+pub struct MixerDetector {
+    exchange_addresses: HashMap<String, String>,
+    config: MixerConfig,
+}
+
+impl MixerDetector {
+    pub fn new(config: MixerConfig) -> Self {
+        Self { exchange_addresses: exchange_addresses(), config }
+    }
+    
+    fn detect_mixer_patterns(&self, account: &str, transactions: &[Transaction]) -> Vec<SuspiciousPattern> {
+        // Exchanges split and pool funds all day long
+        if self.exchange_addresses.contains_key(account) {
+            return Vec::new();
+        }
+        
+        let mut sorted: Vec<&Transaction> = transactions.iter().collect();
+        sorted.sort_by_key(|tx| tx.timestamp);
+        let outgoing: Vec<&Transaction> = sorted.iter().copied().filter(|tx| tx.from == account && tx.to != account).collect();
+        let incoming: Vec<&Transaction> = sorted.iter().copied().filter(|tx| tx.to == account && tx.from != account).collect();
+        
+        let mut patterns = Vec::new();
+        
+        // Fan-out: many similar transfers to fresh accounts, later coming back
+        for fan in self.find_fans(&sorted, &outgoing, |tx| &tx.to) {
+            let recipients: HashSet<&str> = fan.iter().map(|tx| tx.to.as_str()).collect();
+            let end = fan[fan.len() - 1].timestamp;
+            let returns: Vec<&Transaction> = incoming.iter().copied()
+                .filter(|tx| recipients.contains(tx.from.as_str()))
+                .filter(|tx| tx.timestamp > end && tx.timestamp - end <= self.config.converge_window_nanos)
+                .collect();
+            
+            // A payout that never comes back (airdrop, payroll) is not structuring
+            if !returns.is_empty() {
+                let mut evidence = fan.clone();
+                evidence.extend(returns);
+                patterns.push(self.mixer_pattern(account, &fan, evidence));
+            }
+        }
+        
+        // Fan-in: many similar deposits from fresh accounts, consolidated in one transfer
+        for fan in self.find_fans(&sorted, &incoming, |tx| &tx.from) {
+            let total: u64 = fan.iter().map(|tx| tx.amount).sum();
+            let end = fan[fan.len() - 1].timestamp;
+            let consolidation = outgoing.iter().copied().find(|tx| {
+                tx.timestamp >= end
+                    && tx.timestamp - end <= self.config.converge_window_nanos
+                    && tx.amount as f64 >= total as f64 * self.config.min_consolidated_share
+            });
+            
+            if let Some(consolidation) = consolidation {
+                let mut evidence = fan.clone();
+                evidence.push(consolidation);
+                patterns.push(self.mixer_pattern(account, &fan, evidence));
+            }
+        }
+        
+        patterns
+    }
+    
+    /// Groups of at least `min_fan_width` similar-sized transfers within one window,
+    /// each with a counterparty the account had never dealt with before
+    fn find_fans<'a>(
+        &self,
+        history: &[&'a Transaction],
+        candidates: &[&'a Transaction],
+        counterparty: fn(&Transaction) -> &String,
+    ) -> Vec<Vec<&'a Transaction>> {
+        // When each counterparty first appears in the account's history
+        let mut first_seen: HashMap<&str, u64> = HashMap::new();
+        for tx in history {
+            first_seen.entry(tx.from.as_str()).or_insert(tx.timestamp);
+            first_seen.entry(tx.to.as_str()).or_insert(tx.timestamp);
+        }
+        
+        let mut fans = Vec::new();
+        let mut used = vec![false; candidates.len()];
+        
+        for (i, anchor) in candidates.iter().enumerate() {
+            if used[i] {
+                continue;
+            }
+            
+            let mut members = Vec::new();
+            let mut counterparties = HashSet::new();
+            for (j, tx) in candidates.iter().enumerate().skip(i) {
+                if tx.timestamp - anchor.timestamp > self.config.fan_window_nanos {
+                    break;
+                }
+                let party = counterparty(tx);
+                let similar = (tx.amount as f64 - anchor.amount as f64).abs() <= anchor.amount as f64 * self.config.amount_similarity;
+                let fresh = first_seen.get(party.as_str()) == Some(&tx.timestamp);
+                if !used[j] && similar && fresh && counterparties.insert(party.as_str()) {
+                    members.push(j);
+                }
+            }
+            
+            if members.len() >= self.config.min_fan_width {
+                for j in &members {
+                    used[*j] = true;
+                }
+                fans.push(members.iter().map(|j| candidates[*j]).collect());
+            }
+        }
+        
+        fans
+    }
+    
+    fn mixer_pattern(&self, account: &str, fan: &[&Transaction], evidence: Vec<&Transaction>) -> SuspiciousPattern {
+        SuspiciousPattern {
+            account: account.to_string(),
+            pattern_type: PatternType::MixerPattern,
+            withdrawals: Vec::new(),
+            deposits: Vec::new(),
+            total_amount: fan.iter().map(|tx| tx.amount).sum(),
+            holding_periods: Vec::new(),
+            peels: Vec::new(),
+            transfers: evidence.into_iter().cloned().collect(),
+        }
+    }
+}
+
+impl Detector for MixerDetector {
+    fn name(&self) -> &'static str {
+        "mixer"
+    }
+    
+    fn description(&self) -> &'static str {
+        "Similar-sized transfers fanned out to fresh accounts, or fresh deposits consolidated"
+    }
+    
+    fn params(&self) -> Vec<DetectorParam> {
+        vec![
+            DetectorParam {
+                name: "min_fan_width",
+                value: self.config.min_fan_width.to_string(),
+                description: "minimum number of similar transfers in a fan",
+            },
+            DetectorParam {
+                name: "amount_similarity",
+                value: self.config.amount_similarity.to_string(),
+                description: "relative difference allowed between transfers of one fan",
+            },
+            DetectorParam {
+                name: "fan_window_hours",
+                value: (self.config.fan_window_nanos as f64 / NANOS_PER_DAY as f64 * 24.0).to_string(),
+                description: "time span of a single fan",
+            },
+            DetectorParam {
+                name: "converge_window_days",
+                value: (self.config.converge_window_nanos as f64 / NANOS_PER_DAY as f64).to_string(),
+                description: "how long after a fan the funds may reconverge",
+            },
+            DetectorParam {
+                name: "min_consolidated_share",
+                value: self.config.min_consolidated_share.to_string(),
+                description: "share of a fan-in that must leave in one transfer",
+            },
+        ]
+    }
+    
+    fn set_param(&mut self, name: &str, value: &str) -> Result<()> {
+        let detector = self.name();
+        match name {
+            "min_fan_width" => self.config.min_fan_width = parse_param(detector, name, value)?,
+            "amount_similarity" => self.config.amount_similarity = parse_param(detector, name, value)?,
+            "fan_window_hours" => {
+                self.config.fan_window_nanos = (parse_param::<f64>(detector, name, value)? * NANOS_PER_DAY as f64 / 24.0) as u64
+            }
+            "converge_window_days" => {
+                self.config.converge_window_nanos = (parse_param::<f64>(detector, name, value)? * NANOS_PER_DAY as f64) as u64
+            }
+            "min_consolidated_share" => self.config.min_consolidated_share = parse_param(detector, name, value)?,
+            _ => return Err(unknown_param(detector, name)),
+        }
+        Ok(())
+    }
+    
+    fn evidence_schema(&self) -> Vec<EvidenceField> {
+        vec![EvidenceField {
+            field: "transfers",
+            description: "the fan, plus returns from fan-out recipients or the consolidating transfer",
+        }]
+    }
+    
+    fn detect(&self, account: &str, transactions: &[Transaction]) -> Vec<SuspiciousPattern> {
+        self.detect_mixer_patterns(account, transactions)
+    }
+}
+
+//
+// Registry
+//
+
+/// Registry of detectors; each can be enabled, disabled and configured by name
+pub struct PatternDetector {
+    detectors: Vec<(Box<dyn Detector>, bool)>, // (detector, enabled)
+}
+
+/// What `PatternDetector::list` reports for one detector
+#[derive(Debug, Serialize)]
+pub struct DetectorInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub version: &'static str,
+    pub enabled: bool,
+    pub params: Vec<DetectorParam>,
+    pub evidence: Vec<EvidenceField>,
+}
+
+impl PatternDetector {
+    /// Every built-in detector, enabled with its default parameters
+    pub fn new() -> Self {
+        let mut registry = Self { detectors: Vec::new() };
+        registry.register(Box::new(ExchangeCycleDetector::new()));
+        registry.register(Box::new(LargeHoldingDetector::new()));
+        registry.register(Box::new(MixerDetector::new(MixerConfig::default())));
+        registry
+    }
+    
+    pub fn with_mixer_config(mut self, mixer: MixerConfig) -> Self {
+        self.register(Box::new(MixerDetector::new(mixer)));
+        self
+    }
+    
+    /// Add a detector, replacing any registered under the same name
+    pub fn register(&mut self, detector: Box<dyn Detector>) {
+        match self.detectors.iter_mut().find(|(d, _)| d.name() == detector.name()) {
+            Some(slot) => slot.0 = detector,
+            None => self.detectors.push((detector, true)),
+        }
+    }
+    
+    fn find_mut(&mut self, name: &str) -> Result<&mut (Box<dyn Detector>, bool)> {
+        self.detectors.iter_mut().find(|(d, _)| d.name() == name).ok_or_else(|| anyhow!("unknown detector '{}'", name))
+    }
+    
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<()> {
+        self.find_mut(name)?.1 = enabled;
+        Ok(())
+    }
+    
+    /// Enable only the named detectors
+    pub fn enable_only(&mut self, names: &[&str]) -> Result<()> {
+        for name in names {
+            self.find_mut(name)?;
+        }
+        for (detector, enabled) in &mut self.detectors {
+            *enabled = names.contains(&detector.name());
+        }
+        Ok(())
+    }
+    
+    pub fn configure(&mut self, name: &str, param: &str, value: &str) -> Result<()> {
+        self.find_mut(name)?.0.set_param(param, value)
+    }
+    
+    /// Apply "detector.param=value" settings separated by commas
+    pub fn configure_all(&mut self, settings: &str) -> Result<()> {
+        for setting in settings.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = setting.split_once('=').ok_or_else(|| anyhow!("expected detector.param=value, got '{}'", setting))?;
+            let (name, param) = key.split_once('.').ok_or_else(|| anyhow!("expected detector.param, got '{}'", key))?;
+            self.configure(name, param, value)?;
+        }
+        Ok(())
+    }
+    
+    pub fn list(&self) -> Vec<DetectorInfo> {
+        self.detectors
+            .iter()
+            .map(|(detector, enabled)| DetectorInfo {
+                name: detector.name(),
+                description: detector.description(),
+                version: detector.version(),
+                enabled: *enabled,
+                params: detector.params(),
+                evidence: detector.evidence_schema(),
+            })
+            .collect()
+    }
+    
+    pub fn detect_patterns(&self, account: &str, transactions: &[Transaction]) -> Vec<SuspiciousPattern> {
+        self.detect_by_detector(account, transactions).into_iter().map(|(_, pattern)| pattern).collect()
+    }
+    
+    /// Like `detect_patterns`, keeping the name of the detector behind each pattern
+    pub fn detect_by_detector(&self, account: &str, transactions: &[Transaction]) -> Vec<(&dyn Detector, SuspiciousPattern)> {
+        self.detectors
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .flat_map(|(detector, _)| detector.detect(account, transactions).into_iter().map(|p| (detector.as_ref(), p)))
+            .collect()
+    }
+    
+    /// Names of the enabled detectors
+    pub fn enabled(&self) -> Vec<&'static str> {
+        self.detectors.iter().filter(|(_, enabled)| *enabled).map(|(detector, _)| detector.name()).collect()
     }
 }
 
@@ -167,6 +929,10 @@ impl PatternDetector {
 mod tests {
     use super::*;
     
+    fn tx(from: &str, to: &str, amount: u64, timestamp: u64) -> Transaction {
+        Transaction { from: from.to_string(), to: to.to_string(), amount, timestamp, block: 0, fee: 0 }
+    }
+    
     #[test]
     fn test_pattern_detection() {
         let detector = PatternDetector::new();
@@ -178,17 +944,208 @@ mod tests {
                 to: "test_account".to_string(),
                 amount: 1_000_000_000_000, // 10,000 ICP
                 timestamp: 0,
+                block: 0,
+                fee: 0,
             },
             Transaction {
                 from: "test_account".to_string(),
                 to: "609d3e1e45103a82adc97d4f88c51f78dedb25701e8e51e8c4fec53448aadc29".to_string(), // Binance
                 amount: 1_000_000_000_000,
                 timestamp: SIX_WEEKS_NANOS,
+                block: 1,
+                fee: 0,
             },
         ];
         
         let patterns = detector.detect_patterns("test_account", &transactions);
         assert_eq!(patterns.len(), 1);
         assert!(matches!(patterns[0].pattern_type, PatternType::ExchangeCycle));
+        assert_eq!((patterns[0].holding_periods[0].start_block, patterns[0].holding_periods[0].end_block), (0, 1));
+    }
+    
+    #[test]
+    fn test_exchange_cycle_matching() {
+        let coinbase = "449ce7ad1298e2ed2781ed379aba25efc2748d14c60ede190ad7621724b9e8b2";
+        let binance = "609d3e1e45103a82adc97d4f88c51f78dedb25701e8e51e8c4fec53448aadc29";
+        let day = 24 * 60 * 60 * 1_000_000_000;
+        let transactions = vec![
+            tx(coinbase, "holder", 100, 0),
+            tx(coinbase, "holder", 50, day),
+            // A greedy match would pair the first withdrawal with the first deposit
+            tx("holder", binance, 50, 42 * day),
+            tx("holder", binance, 99, 43 * day),
+            // One withdrawal split over two deposits
+            tx(coinbase, "holder", 300, 100 * day),
+            tx("holder", coinbase, 200, 142 * day),
+            tx("holder", coinbase, 100, 143 * day),
+        ];
+        
+        let detector = ExchangeCycleDetector::new();
+        let patterns = detector.detect("holder", &transactions);
+        assert_eq!(patterns.len(), 1);
+        let periods: Vec<(u64, u64, u64)> = patterns[0]
+            .holding_periods
+            .iter()
+            .map(|hp| (hp.start_timestamp / day, hp.end_timestamp / day, hp.amount_held))
+            .collect();
+        assert_eq!(periods, vec![(0, 43, 99), (1, 42, 50), (100, 142, 200), (100, 143, 100)]);
+        
+        // Only the split stays on the same exchange
+        let mut detector = ExchangeCycleDetector::new();
+        detector.set_param("cross_exchange", "false").unwrap();
+        let patterns = detector.detect("holder", &transactions);
+        assert_eq!(patterns[0].total_amount, 300);
+        
+        // Unrelated amounts are not matched at all
+        detector.set_param("max_split", "1").unwrap();
+        assert!(detector.detect("holder", &transactions).is_empty());
+        
+        // Absurd holding times saturate instead of overflowing
+        detector.set_param("holding_days", "1e12").unwrap();
+        assert!(detector.detect("holder", &transactions).is_empty());
+    }
+    
+    struct SelfTransferDetector;
+    
+    impl Detector for SelfTransferDetector {
+        fn name(&self) -> &'static str {
+            "self_transfer"
+        }
+        
+        fn description(&self) -> &'static str {
+            "Transfers from an account to itself"
+        }
+        
+        fn params(&self) -> Vec<DetectorParam> {
+            Vec::new()
+        }
+        
+        fn set_param(&mut self, name: &str, _value: &str) -> Result<()> {
+            Err(unknown_param(self.name(), name))
+        }
+        
+        fn evidence_schema(&self) -> Vec<EvidenceField> {
+            vec![EvidenceField { field: "transfers", description: "the self transfers" }]
+        }
+        
+        fn detect(&self, account: &str, transactions: &[Transaction]) -> Vec<SuspiciousPattern> {
+            let transfers: Vec<Transaction> =
+                transactions.iter().filter(|tx| tx.from == account && tx.to == account).cloned().collect();
+            if transfers.is_empty() {
+                return Vec::new();
+            }
+            vec![SuspiciousPattern {
+                account: account.to_string(),
+                pattern_type: PatternType::Custom(self.name().to_string()),
+                withdrawals: Vec::new(),
+                deposits: Vec::new(),
+                total_amount: transfers.iter().map(|tx| tx.amount).sum(),
+                holding_periods: Vec::new(),
+                peels: Vec::new(),
+                transfers,
+            }]
+        }
+    }
+    
+    #[test]
+    fn test_registry() {
+        let mut detector = PatternDetector::new();
+        detector.register(Box::new(SelfTransferDetector));
+        assert_eq!(detector.list().len(), 4);
+        
+        detector.configure_all("mixer.min_fan_width=3, large_holding.min_days=14").unwrap();
+        let mixer = detector.list().into_iter().find(|d| d.name == "mixer").unwrap();
+        assert_eq!(mixer.params[0].value, "3");
+        assert!(detector.configure("mixer", "nonsense", "1").is_err());
+        assert!(detector.configure("unknown", "min_days", "1").is_err());
+        
+        let transactions = vec![tx("a", "a", 7, 0)];
+        let patterns = detector.detect_patterns("a", &transactions);
+        assert_eq!(patterns.len(), 1);
+        assert!(matches!(&patterns[0].pattern_type, PatternType::Custom(name) if name == "self_transfer"));
+        
+        detector.enable_only(&["exchange_cycle"]).unwrap();
+        assert!(detector.detect_patterns("a", &transactions).is_empty());
+    }
+    
+    #[test]
+    fn test_large_holding_detection() {
+        let detector = PatternDetector::new();
+        let icp = 100_000_000;
+        let day = 24 * 60 * 60 * 1_000_000_000;
+        let transactions = vec![
+            tx("whale", "holder", 20_000 * icp, 0),
+            tx("other", "holder", 5 * icp, 10 * day),
+            tx("holder", "out", 15_000 * icp, 45 * day),
+            tx("holder", "out", 5_000 * icp, 46 * day),
+            // Moved after a single day: not a holding period
+            tx("whale", "holder", 12_000 * icp, 50 * day),
+            tx("holder", "out", 12_000 * icp, 51 * day),
+        ];
+        
+        let patterns = detector.detect_patterns("holder", &transactions);
+        assert_eq!(patterns.len(), 1);
+        assert!(matches!(patterns[0].pattern_type, PatternType::LargeHolding));
+        
+        let periods = &patterns[0].holding_periods;
+        assert_eq!(periods.len(), 1);
+        // The later 5 ICP receipt is part of what was held
+        assert_eq!(periods[0].amount_held, 20_005 * icp);
+        assert_eq!(periods[0].duration_days, 45.0);
+        
+        // A balance built up from several receipts qualifies once it crosses the threshold
+        let built_up = vec![
+            tx("a", "saver", 6_000 * icp, 0),
+            tx("b", "saver", 6_000 * icp, day),
+            tx("saver", "out", 12_000 * icp, 41 * day),
+        ];
+        let patterns = detector.detect_patterns("saver", &built_up);
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].holding_periods[0].duration_days, 40.0);
+        assert_eq!(patterns[0].holding_periods[0].amount_held, 12_000 * icp);
+        
+        // Fees count: what is left after this transfer is below the threshold
+        let with_fee = vec![
+            tx("a", "payer", 10_002 * icp, 0),
+            Transaction { fee: 2 * icp, ..tx("payer", "out", icp, day) },
+            tx("payer", "out", 9_999 * icp, 60 * day),
+        ];
+        assert!(detector.detect_patterns("payer", &with_fee).is_empty());
+    }
+    
+    #[test]
+    fn test_mixer_detection() {
+        let detector = PatternDetector::new().with_mixer_config(MixerConfig { min_fan_width: 3, ..MixerConfig::default() });
+        // Split into three similar transfers to fresh accounts, which send the funds back
+        let fan_out = vec![
+            tx("source", "mixer", 3_000, 0),
+            tx("mixer", "a", 1_000, 10),
+            tx("mixer", "b", 990, 20),
+            tx("mixer", "c", 1_010, 30),
+            tx("a", "mixer", 990, 1_000),
+            tx("b", "mixer", 980, 1_100),
+        ];
+        let patterns = detector.detect_patterns("mixer", &fan_out);
+        assert_eq!(patterns.len(), 1);
+        assert!(matches!(patterns[0].pattern_type, PatternType::MixerPattern));
+        assert_eq!(patterns[0].total_amount, 3_000);
+        assert_eq!(patterns[0].transfers.len(), 5);
+        
+        // Without the returns it is an ordinary payout
+        assert!(detector.detect_patterns("mixer", &fan_out[..4]).is_empty());
+        
+        // Three small deposits consolidated in one transfer
+        let fan_in = vec![
+            tx("x", "pool", 500, 0),
+            tx("y", "pool", 510, 5),
+            tx("z", "pool", 495, 9),
+            tx("pool", "out", 1_500, 100),
+        ];
+        let patterns = detector.detect_patterns("pool", &fan_in);
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].transfers.len(), 4);
+        
+        // Without the consolidation the deposits are not suspicious
+        assert!(detector.detect_patterns("pool", &fan_in[..3]).is_empty());
     }
 }
\ No newline at end of file
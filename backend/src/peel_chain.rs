@@ -0,0 +1,274 @@
+// Peel-chain detection over ledger.db
+// Follows the dominant outflow hop by hop, recording every smaller transfer peeled off to a side account
+
+use crate::{
+    entities::label_map,
+    ledger_db::{LedgerDatabase, NANOS_PER_DAY},
+    pattern_detector::{PatternType, Peel, SuspiciousPattern},
+    transfer_graph::{GraphFilter, TransferEdge, TransferGraph},
+    Type,
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone)]
+pub struct PeelConfig {
+    /// Chains with fewer hops are not reported
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Share of a hop's outflow the forwarded transfer must carry
+    pub min_forward_share: f64,
+    /// How long after receiving the funds an account may take to forward them
+    pub hop_window_nanos: u64,
+    /// Smallest transfer that can start a chain
+    pub min_start_amount: u64,
+}
+
+impl Default for PeelConfig {
+    fn default() -> Self {
+        Self {
+            min_length: 5,
+            max_length: 1000,
+            min_forward_share: 0.75,
+            hop_window_nanos: 7 * NANOS_PER_DAY,
+            min_start_amount: 100 * 100_000_000,
+        }
+    }
+}
+
+/// A detected chain: the forwarded transfers in order and the pattern built from them
+#[derive(Debug, Serialize)]
+pub struct PeelChain {
+    pub pattern: SuspiciousPattern,
+    pub hops: Vec<TransferEdge>,
+    pub final_account: String,
+    pub final_amount: u64,
+    pub peeled_total: u64,
+    pub peel_destination_labels: Vec<(String, String)>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PeelChainReport {
+    pub transfers_scanned: usize,
+    pub min_length: usize,
+    pub chains: Vec<PeelChain>,
+}
+
+/// Follow a chain from `account`, entered through the transfer at `entry` (if any)
+///
+/// Returns the indices of the forwarded transfers and the peels taken at each hop.
+fn follow(
+    graph: &TransferGraph,
+    config: &PeelConfig,
+    account: &str,
+    entry: Option<&TransferEdge>,
+) -> (Vec<usize>, Vec<Peel>) {
+    let mut hops = Vec::new();
+    let mut peels = Vec::new();
+    let mut visited: HashSet<&str> = HashSet::from([account]);
+    let mut current = account;
+    let mut after = entry.map(|e| (e.timestamp, e.block));
+
+    while hops.len() < config.max_length {
+        let indices = graph.outgoing_indices(current);
+        let first = match after {
+            Some(key) => indices.partition_point(|i| (graph.edges[*i].timestamp, graph.edges[*i].block) <= key),
+            None => 0,
+        };
+        let Some(&opening) = indices.get(first) else { break };
+        let start = after.map(|(ts, _)| ts).unwrap_or(graph.edges[opening].timestamp);
+        let deadline = start.saturating_add(config.hop_window_nanos);
+
+        let window: Vec<usize> =
+            indices[first..].iter().copied().take_while(|i| graph.edges[*i].timestamp <= deadline).collect();
+        let Some(&dominant) = window.iter().max_by_key(|i| (graph.edges[**i].amount, std::cmp::Reverse(**i))) else {
+            break;
+        };
+        let total: u64 = window.iter().map(|i| graph.edges[*i].amount).sum();
+        let forwarded = &graph.edges[dominant];
+
+        // A hop without a peel, or one that splits the funds evenly, ends the chain
+        if window.len() < 2 || (forwarded.amount as f64) < total as f64 * config.min_forward_share {
+            break;
+        }
+        if !visited.insert(forwarded.to.as_str()) {
+            break;
+        }
+
+        for &i in window.iter().filter(|i| **i != dominant) {
+            let edge = &graph.edges[i];
+            peels.push(Peel {
+                hop: hops.len(),
+                account: current.to_string(),
+                destination: edge.to.clone(),
+                amount: edge.amount,
+                timestamp: edge.timestamp,
+                block: edge.block,
+            });
+        }
+
+        hops.push(dominant);
+        after = Some((forwarded.timestamp, forwarded.block));
+        current = forwarded.to.as_str();
+    }
+
+    (hops, peels)
+}
+
+fn build_chain(
+    graph: &TransferGraph,
+    start: &str,
+    hops: &[usize],
+    peels: Vec<Peel>,
+    labels: &HashMap<String, (String, Type)>,
+) -> PeelChain {
+    let hops: Vec<TransferEdge> = hops.iter().map(|i| graph.edges[*i].clone()).collect();
+    let first = &hops[0];
+    let last = &hops[hops.len() - 1];
+    let peeled_total = peels.iter().map(|p| p.amount).sum();
+    let peel_destination_labels = peels
+        .iter()
+        .filter_map(|p| {
+            labels.get(&p.destination).map(|(name, ty)| (p.destination.clone(), format!("{} ({})", name, ty)))
+        })
+        .collect();
+
+    PeelChain {
+        pattern: SuspiciousPattern {
+            account: start.to_string(),
+            pattern_type: PatternType::PeelChain,
+            withdrawals: Vec::new(),
+            deposits: Vec::new(),
+            total_amount: first.amount + peels.iter().filter(|p| p.hop == 0).map(|p| p.amount).sum::<u64>(),
+            holding_periods: Vec::new(),
+            peels,
+            transfers: Vec::new(),
+        },
+        final_account: last.to.clone(),
+        final_amount: last.amount,
+        peeled_total,
+        peel_destination_labels,
+        hops,
+    }
+}
+
+/// Find peel chains starting at `starts`, or at every large enough transfer when `starts` is None
+pub fn find_peel_chains(graph: &TransferGraph, config: &PeelConfig, starts: Option<&[String]>) -> Vec<PeelChain> {
+    let mut chains = Vec::new();
+    // Transfers already forwarded inside a reported chain; starting there would report a suffix of it
+    let mut covered: HashSet<usize> = HashSet::new();
+    let labels = label_map();
+
+    let mut record = |start: &str, entry: Option<&TransferEdge>, covered: &mut HashSet<usize>| {
+        let (hops, peels) = follow(graph, config, start, entry);
+        if hops.len() >= config.min_length.max(1) {
+            covered.extend(hops.iter().copied());
+            chains.push(build_chain(graph, start, &hops, peels, &labels));
+        }
+    };
+
+    match starts {
+        Some(accounts) => {
+            for account in accounts {
+                record(account, None, &mut covered);
+                for edge in graph.incoming(account).filter(|e| e.amount >= config.min_start_amount) {
+                    record(account, Some(edge), &mut covered);
+                }
+            }
+        }
+        None => {
+            for (i, edge) in graph.edges.iter().enumerate() {
+                if edge.amount >= config.min_start_amount && !covered.contains(&i) {
+                    record(&edge.to, Some(edge), &mut covered);
+                }
+            }
+        }
+    }
+
+    // The same chain can be reached from several entries of one start account
+    let mut seen = HashSet::new();
+    chains.retain(|c| seen.insert(c.hops.iter().map(|h| h.block).collect::<Vec<_>>()));
+    chains
+}
+
+/// Run peel-chain detection over ledger.db
+pub async fn run_peel_chains(
+    db_path: &str,
+    filter: GraphFilter,
+    config: PeelConfig,
+    starts: Option<Vec<String>>,
+) -> Result<()> {
+    println!("===== PEEL CHAIN DETECTION =====");
+    println!("Database: {}", db_path);
+    println!(
+        "Min length: {} hops, forward share >= {:.0}%, hop window: {} days",
+        config.min_length,
+        config.min_forward_share * 100.0,
+        config.hop_window_nanos / NANOS_PER_DAY
+    );
+
+    let db = LedgerDatabase::new(db_path)?;
+    let graph = TransferGraph::load(&db, &filter)?;
+    println!("Loaded {} transfers", graph.edges.len());
+
+    let mut chains = find_peel_chains(&graph, &config, starts.as_deref());
+    chains.sort_by_key(|c| std::cmp::Reverse(c.hops.len()));
+
+    println!("\nPeel chains found: {}", chains.len());
+    for chain in chains.iter().take(20) {
+        println!(
+            "  {} -> {}: {} hops, {} ICP in, {} ICP peeled, {} peels",
+            &chain.pattern.account[..8.min(chain.pattern.account.len())],
+            &chain.final_account[..8.min(chain.final_account.len())],
+            chain.hops.len(),
+            chain.pattern.total_amount as f64 / 100_000_000.0,
+            chain.peeled_total as f64 / 100_000_000.0,
+            chain.pattern.peels.len()
+        );
+    }
+
+    let report = PeelChainReport { transfers_scanned: graph.edges.len(), min_length: config.min_length, chains };
+
+    let file_name = "./peel_chains.json";
+    std::fs::write(file_name, serde_json::to_string_pretty(&report)?)?;
+
+    println!("\nPeel chains saved to: {}", file_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(block: u64, from: &str, to: &str, amount: u64) -> TransferEdge {
+        TransferEdge { block, from: from.to_string(), to: to.to_string(), amount, timestamp: block * 1_000_000_000 }
+    }
+
+    #[test]
+    fn test_peel_chain() {
+        let graph = TransferGraph::from_edges(vec![
+            edge(1, "source", "a", 1000),
+            edge(2, "a", "side1", 50),
+            edge(3, "a", "b", 940),
+            edge(4, "b", "side2", 40),
+            edge(5, "b", "c", 890),
+            edge(6, "c", "side3", 30),
+            edge(7, "c", "d", 850),
+            edge(8, "d", "e", 420), // even split ends the chain
+            edge(9, "d", "f", 420),
+        ]);
+        let config = PeelConfig { min_length: 3, min_start_amount: 500, ..PeelConfig::default() };
+
+        let chains = find_peel_chains(&graph, &config, None);
+        assert_eq!(chains.len(), 1);
+        let chain = &chains[0];
+        assert_eq!(chain.pattern.account, "a");
+        assert_eq!(chain.hops.iter().map(|h| h.block).collect::<Vec<_>>(), vec![3, 5, 7]);
+        assert_eq!(chain.final_account, "d");
+        assert_eq!(chain.pattern.peels.len(), 3);
+        assert_eq!(chain.pattern.peels[1].destination, "side2");
+        assert_eq!(chain.peeled_total, 120);
+    }
+}
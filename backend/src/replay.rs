@@ -0,0 +1,447 @@
+// Ledger replay over ledger.db
+// Rebuilds the balance of every account by applying transactions in block order,
+// optionally checking the ledger's invariants along the way
+
+use crate::ledger_db::{DbTransaction, LedgerDatabase, NANOS_PER_DAY};
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// A point in the ledger's history, given as a block index or a UTC date
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointInTime {
+    Block(u64),
+    Date(NaiveDate),
+}
+
+impl PointInTime {
+    /// Parse "YYYY-MM-DD" as a date, anything else as a block index
+    pub fn parse(value: &str) -> Result<Self> {
+        if value.contains('-') {
+            let date =
+                NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| anyhow!("invalid date '{}': {}", value, e))?;
+            Ok(Self::Date(date))
+        } else {
+            let block = value.parse().map_err(|e| anyhow!("invalid block '{}': {}", value, e))?;
+            Ok(Self::Block(block))
+        }
+    }
+
+    /// Resolve to the last block included; a date includes every block of that day
+    pub fn resolve_block(&self, db: &LedgerDatabase) -> Result<Option<u64>> {
+        match self {
+            Self::Block(block) => Ok(Some(*block)),
+            Self::Date(date) => {
+                let start_of_day =
+                    date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_nanos_opt().unwrap_or(0) as u64;
+                db.get_block_at_timestamp(start_of_day + NANOS_PER_DAY - 1)
+            }
+        }
+    }
+}
+
+/// Balances and supply counters after replaying a prefix of the ledger
+#[derive(Debug, Default)]
+pub struct LedgerState {
+    pub balances: HashMap<String, i64>,
+    pub minted: u64,
+    pub burned: u64,
+    pub fees_burned: u64,
+    pub last_block: Option<u64>,
+    pub last_timestamp: Option<u64>,
+}
+
+impl LedgerState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply a single transaction
+    pub fn apply(&mut self, tx: &DbTransaction) {
+        let amount = tx.amount.unwrap_or(0);
+        let fee = tx.fee.unwrap_or(0);
+
+        match tx.operation_type.as_str() {
+            "Transfer" => {
+                if let Some(from) = &tx.from_account {
+                    *self.balances.entry(from.clone()).or_insert(0) -= (amount + fee) as i64;
+                }
+                if let Some(to) = &tx.to_account {
+                    *self.balances.entry(to.clone()).or_insert(0) += amount as i64;
+                }
+                self.fees_burned += fee;
+            }
+            "Mint" => {
+                if let Some(to) = &tx.to_account {
+                    *self.balances.entry(to.clone()).or_insert(0) += amount as i64;
+                }
+                self.minted += amount;
+            }
+            "Burn" => {
+                if let Some(from) = &tx.from_account {
+                    *self.balances.entry(from.clone()).or_insert(0) -= amount as i64;
+                }
+                self.burned += amount;
+            }
+            "Approve" => {
+                if let Some(from) = &tx.from_account {
+                    *self.balances.entry(from.clone()).or_insert(0) -= fee as i64;
+                }
+                self.fees_burned += fee;
+            }
+            _ => {}
+        }
+
+        self.last_block = Some(tx.id);
+        if tx.timestamp.is_some() {
+            self.last_timestamp = tx.timestamp;
+        }
+    }
+
+    /// Total supply implied by the supply counters
+    pub fn total_supply(&self) -> i64 {
+        self.minted as i64 - self.burned as i64 - self.fees_burned as i64
+    }
+
+    /// Accounts holding a positive balance
+    pub fn positive_balances(&self) -> impl Iterator<Item = (&String, u64)> {
+        self.balances.iter().filter(|(_, balance)| **balance > 0).map(|(account, balance)| (account, *balance as u64))
+    }
+}
+
+/// Replay ledger.db from the first block up to and including `last_block`
+pub fn replay_to(db: &LedgerDatabase, last_block: Option<u64>) -> Result<LedgerState> {
+    let mut state = LedgerState::new();
+    let mut applied = 0u64;
+
+    db.for_each_transaction(last_block, |tx| {
+        state.apply(&tx);
+        applied += 1;
+        if applied.is_multiple_of(1_000_000) {
+            println!("  Replayed {} transactions...", applied);
+        }
+        Ok(())
+    })?;
+
+    Ok(state)
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub enum ViolationKind {
+    NegativeBalance,
+    BlockGap,
+    TimestampRegression,
+    SupplyMismatch,
+    AllowanceOverspent,
+    MissingApproval,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Violation {
+    pub block: u64,
+    pub kind: ViolationKind,
+    pub account: Option<String>,
+    pub detail: String,
+}
+
+/// Replays the ledger while checking that
+/// - no balance goes negative
+/// - blocks are contiguous and their timestamps never go backwards, so nothing is missing from the import
+/// - the replayed supply (minted - burned - fees) matches the supply the ledger reports
+/// - transfer_from never spends more than the spender was approved for
+#[derive(Debug, Default)]
+pub struct InvariantChecker {
+    pub state: LedgerState,
+    // (owner, spender) -> remaining allowance
+    allowances: HashMap<(String, String), u64>,
+    // Approvals imported without their allowance; spends against them cannot be checked
+    unknown_allowances: HashSet<(String, String)>,
+    pub violations: Vec<Violation>,
+}
+
+impl InvariantChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply(&mut self, tx: &DbTransaction) {
+        self.check_continuity(tx);
+
+        if tx.operation_type == "Approve" {
+            self.record_approval(tx);
+        } else if tx.operation_type == "Transfer" && tx.spender.is_some() {
+            self.check_transfer_from(tx);
+        }
+
+        // Balances before the transaction, to report only the moment an account goes negative
+        let touched: Vec<(String, i64)> = [&tx.from_account, &tx.to_account]
+            .into_iter()
+            .flatten()
+            .map(|account| (account.clone(), self.state.balances.get(account).copied().unwrap_or(0)))
+            .collect();
+
+        self.state.apply(tx);
+
+        for (account, before) in touched {
+            let after = self.state.balances.get(&account).copied().unwrap_or(0);
+            if after < 0 && before >= 0 {
+                self.violations.push(Violation {
+                    block: tx.id,
+                    kind: ViolationKind::NegativeBalance,
+                    detail: format!("balance went from {} to {} e8s", before, after),
+                    account: Some(account),
+                });
+            }
+        }
+    }
+
+    /// Report blocks missing before `tx` and timestamps that run backwards
+    fn check_continuity(&mut self, tx: &DbTransaction) {
+        if let Some(last) = self.state.last_block {
+            if tx.id > last + 1 {
+                self.violations.push(Violation {
+                    block: tx.id,
+                    kind: ViolationKind::BlockGap,
+                    account: None,
+                    detail: format!("blocks {} to {} are missing", last + 1, tx.id - 1),
+                });
+            }
+        }
+        if let (Some(last), Some(timestamp)) = (self.state.last_timestamp, tx.timestamp) {
+            if timestamp < last {
+                self.violations.push(Violation {
+                    block: tx.id,
+                    kind: ViolationKind::TimestampRegression,
+                    account: None,
+                    detail: format!("timestamp {} is before the previous block's {}", timestamp, last),
+                });
+            }
+        }
+    }
+
+    fn record_approval(&mut self, tx: &DbTransaction) {
+        if let (Some(owner), Some(spender)) = (&tx.from_account, &tx.spender) {
+            let key = (owner.clone(), spender.clone());
+            // Older imports have no allowance value; spends against those approvals are not checked
+            match tx.allowance {
+                Some(allowance) => {
+                    self.unknown_allowances.remove(&key);
+                    self.allowances.insert(key, allowance);
+                }
+                None => {
+                    self.allowances.remove(&key);
+                    self.unknown_allowances.insert(key);
+                }
+            }
+        }
+    }
+
+    fn check_transfer_from(&mut self, tx: &DbTransaction) {
+        let (Some(owner), Some(spender)) = (&tx.from_account, &tx.spender) else {
+            return;
+        };
+
+        // The allowance covers both the amount and the fee
+        let spent = tx.amount.unwrap_or(0) + tx.fee.unwrap_or(0);
+        let key = (owner.clone(), spender.clone());
+        if self.unknown_allowances.contains(&key) {
+            return;
+        }
+
+        match self.allowances.get_mut(&key) {
+            Some(remaining) if *remaining >= spent => *remaining -= spent,
+            Some(remaining) => {
+                self.violations.push(Violation {
+                    block: tx.id,
+                    kind: ViolationKind::AllowanceOverspent,
+                    account: Some(owner.clone()),
+                    detail: format!("spender {} spent {} e8s with {} e8s approved", spender, spent, remaining),
+                });
+                *remaining = 0;
+            }
+            None => self.violations.push(Violation {
+                block: tx.id,
+                kind: ViolationKind::MissingApproval,
+                account: Some(owner.clone()),
+                detail: format!("spender {} spent {} e8s without a recorded approval", spender, spent),
+            }),
+        }
+    }
+
+    /// Compare the replayed supply against the total supply the ledger reports at the same block
+    ///
+    /// Balances and supply counters move together in `LedgerState::apply`, so only an outside figure
+    /// such as icrc1_total_supply can reveal transactions missing from the import.
+    pub fn check_supply(&mut self, reported_supply: u64) {
+        let total_supply = self.state.total_supply();
+
+        if total_supply != reported_supply as i64 {
+            self.violations.push(Violation {
+                block: self.state.last_block.unwrap_or(0),
+                kind: ViolationKind::SupplyMismatch,
+                account: None,
+                detail: format!(
+                    "minted {} - burned {} - fees {} = {} e8s, ledger reports {} e8s",
+                    self.state.minted, self.state.burned, self.state.fees_burned, total_supply, reported_supply
+                ),
+            });
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplayReport {
+    pub last_block: Option<u64>,
+    pub transactions_replayed: u64,
+    pub total_supply_e8s: i64,
+    pub minted_e8s: u64,
+    pub burned_e8s: u64,
+    pub fees_burned_e8s: u64,
+    pub negative_balance_accounts: usize,
+    pub violation_counts: HashMap<String, usize>,
+    pub violations: Vec<Violation>,
+}
+
+/// Replay ledger.db with invariant checks, reporting progress every `checkpoint` blocks
+///
+/// `reported_supply` is the ledger's own total supply at `last_block` (or the tip), when known.
+pub fn replay_with_invariants(
+    db: &LedgerDatabase,
+    last_block: Option<u64>,
+    checkpoint: u64,
+    reported_supply: Option<u64>,
+) -> Result<ReplayReport> {
+    let mut checker = InvariantChecker::new();
+    let mut applied = 0u64;
+
+    db.for_each_transaction(last_block, |tx| {
+        checker.apply(&tx);
+        applied += 1;
+        if applied.is_multiple_of(checkpoint) {
+            println!("  Replayed {} transactions, {} violations so far...", applied, checker.violations.len());
+        }
+        Ok(())
+    })?;
+    if let Some(reported_supply) = reported_supply {
+        checker.check_supply(reported_supply);
+    }
+
+    let mut violation_counts = HashMap::new();
+    for violation in &checker.violations {
+        *violation_counts.entry(format!("{:?}", violation.kind)).or_insert(0) += 1;
+    }
+
+    Ok(ReplayReport {
+        last_block: checker.state.last_block,
+        transactions_replayed: applied,
+        total_supply_e8s: checker.state.total_supply(),
+        minted_e8s: checker.state.minted,
+        burned_e8s: checker.state.burned,
+        fees_burned_e8s: checker.state.fees_burned,
+        negative_balance_accounts: checker.state.balances.values().filter(|b| **b < 0).count(),
+        violation_counts,
+        violations: checker.violations,
+    })
+}
+
+/// Run the invariant-checking replay against ledger.db
+pub async fn run_replay(
+    db_path: &str,
+    last_block: Option<u64>,
+    checkpoint: u64,
+    reported_supply: Option<u64>,
+) -> Result<()> {
+    println!("===== LEDGER REPLAY =====");
+    println!("Database: {}", db_path);
+
+    let db = LedgerDatabase::new(db_path)?;
+    let report = replay_with_invariants(&db, last_block, checkpoint.max(1), reported_supply)?;
+
+    println!("\nReplayed {} transactions up to block {:?}", report.transactions_replayed, report.last_block);
+    println!("Total supply: {} ICP", report.total_supply_e8s as f64 / 100_000_000.0);
+    println!("Accounts with negative balance: {}", report.negative_balance_accounts);
+
+    if report.violations.is_empty() {
+        println!("All invariants hold.");
+    } else {
+        println!("\nInvariant violations:");
+        for (kind, count) in &report.violation_counts {
+            println!("  {}: {}", kind, count);
+        }
+        println!("\nFirst violations:");
+        for violation in report.violations.iter().take(20) {
+            println!("  block {} {:?}: {}", violation.block, violation.kind, violation.detail);
+        }
+    }
+
+    let file_name = "./replay_violations.json";
+    std::fs::write(file_name, serde_json::to_string_pretty(&report)?)?;
+
+    println!("\nReplay report saved to: {}", file_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger_db::test_transaction;
+
+    fn tx(id: u64, op: &str, from: Option<&str>, to: Option<&str>, amount: u64, fee: u64) -> DbTransaction {
+        DbTransaction { fee: Some(fee), ..test_transaction(id, op, from, to, amount) }
+    }
+
+    #[test]
+    fn test_negative_balance_and_supply() {
+        let mut checker = InvariantChecker::new();
+        checker.apply(&tx(1, "Mint", None, Some("a"), 100, 0));
+        checker.apply(&tx(2, "Transfer", Some("a"), Some("b"), 90, 10));
+        checker.check_supply(90);
+        assert!(checker.violations.is_empty());
+        assert_eq!(checker.state.total_supply(), 90);
+
+        // "c" was never funded, e.g. because its inflow is missing from the import
+        checker.apply(&tx(3, "Transfer", Some("c"), Some("b"), 5, 10));
+        assert_eq!(checker.violations.len(), 1);
+        assert_eq!(checker.violations[0].kind, ViolationKind::NegativeBalance);
+        assert_eq!(checker.violations[0].block, 3);
+
+        // The import skipped blocks 4 and 5, including a mint the ledger knows about
+        checker.apply(&tx(6, "Transfer", Some("b"), Some("a"), 5, 10));
+        checker.check_supply(1_070);
+        let kinds: Vec<_> = checker.violations[1..].iter().map(|v| v.kind.clone()).collect();
+        assert_eq!(kinds, vec![ViolationKind::BlockGap, ViolationKind::SupplyMismatch]);
+        assert_eq!(checker.violations[1].block, 6);
+    }
+
+    #[test]
+    fn test_allowance_overspend() {
+        let mut checker = InvariantChecker::new();
+        checker.apply(&tx(1, "Mint", None, Some("owner"), 1_000, 0));
+
+        let mut approve = tx(2, "Approve", Some("owner"), None, 0, 10);
+        approve.spender = Some("spender".to_string());
+        approve.allowance = Some(100);
+        checker.apply(&approve);
+
+        let mut transfer_from = tx(3, "Transfer", Some("owner"), Some("x"), 80, 10);
+        transfer_from.spender = Some("spender".to_string());
+        checker.apply(&transfer_from);
+        assert!(checker.violations.is_empty());
+
+        transfer_from.id = 4;
+        checker.apply(&transfer_from);
+        assert_eq!(checker.violations.len(), 1);
+        assert_eq!(checker.violations[0].kind, ViolationKind::AllowanceOverspent);
+
+        // An approval imported without its allowance leaves later spends unchecked
+        let mut unknown = tx(5, "Approve", Some("owner"), None, 0, 10);
+        unknown.spender = Some("spender".to_string());
+        checker.apply(&unknown);
+        transfer_from.id = 6;
+        transfer_from.timestamp = Some(6);
+        checker.apply(&transfer_from);
+        assert_eq!(checker.violations.len(), 1);
+    }
+}
@@ -0,0 +1,408 @@
+// Per-account risk scoring
+// Combines pattern findings, taint exposure, counterparty labels, account age and behaviour into a
+// 0-100 score, keeping each factor's value and a plain-language reason so the score can be defended
+
+use crate::{
+    entities::label_map,
+    findings::FindingStatus,
+    ledger_db::{DbFinding, DbTransaction, FindingFilter, LedgerDatabase, NANOS_PER_DAY},
+    taint::TaintPoint,
+    Type,
+};
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Relative weight of each factor; they are normalised, so only the ratios matter
+#[derive(Debug, Clone, Serialize)]
+pub struct RiskWeights {
+    pub patterns: f64,
+    pub taint: f64,
+    pub counterparties: f64,
+    pub age: f64,
+    pub behaviour: f64,
+}
+
+impl Default for RiskWeights {
+    fn default() -> Self {
+        Self { patterns: 0.35, taint: 0.25, counterparties: 0.2, age: 0.1, behaviour: 0.1 }
+    }
+}
+
+impl RiskWeights {
+    /// Override defaults from "factor=weight" pairs separated by commas
+    pub fn parse(value: &str) -> Result<Self> {
+        let mut weights = Self::default();
+        for setting in value.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (name, weight) =
+                setting.split_once('=').ok_or_else(|| anyhow!("expected factor=weight, got '{}'", setting))?;
+            let weight: f64 = weight.parse().map_err(|_| anyhow!("invalid weight '{}' for {}", weight, name))?;
+            if !weight.is_finite() || weight < 0.0 {
+                bail!("weight for {} must be a finite, non-negative number", name);
+            }
+            match name {
+                "patterns" => weights.patterns = weight,
+                "taint" => weights.taint = weight,
+                "counterparties" => weights.counterparties = weight,
+                "age" => weights.age = weight,
+                "behaviour" | "behavior" => weights.behaviour = weight,
+                other => {
+                    bail!("unknown factor '{}', expected patterns, taint, counterparties, age or behaviour", other)
+                }
+            }
+        }
+        Ok(weights)
+    }
+
+    fn total(&self) -> f64 {
+        self.patterns + self.taint + self.counterparties + self.age + self.behaviour
+    }
+}
+
+/// The per-account rows of a taint_report.json
+#[derive(Debug, Clone, Deserialize)]
+pub struct TaintExposure {
+    pub account: String,
+    pub tainted_e8s: u64,
+    pub taint_ratio: f64,
+    #[serde(default)]
+    pub history: Vec<TaintPoint>,
+}
+
+#[derive(Deserialize)]
+struct TaintFile {
+    accounts: Vec<TaintExposure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RiskFactor {
+    pub name: &'static str,
+    /// 0 (no risk) to 1 (maximum risk)
+    pub value: f64,
+    pub weight: f64,
+    /// Points this factor adds to the 0-100 score
+    pub contribution: f64,
+    pub explanation: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RiskScore {
+    pub account: String,
+    pub label: Option<String>,
+    pub score: f64,
+    pub factors: Vec<RiskFactor>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RiskReport {
+    pub weights: RiskWeights,
+    pub accounts: Vec<RiskScore>,
+}
+
+/// Everything known about one account that the score is built from
+pub struct AccountEvidence<'a> {
+    pub account: &'a str,
+    pub transactions: &'a [DbTransaction],
+    pub findings: &'a [DbFinding],
+    pub taint: Option<&'a TaintExposure>,
+    /// Ages are measured back from this timestamp, normally the last block's
+    pub reference_timestamp: u64,
+}
+
+const ICP: f64 = 100_000_000.0;
+const RISKY_TYPES: [Type; 2] = [Type::Suspect, Type::Spammer];
+
+fn pattern_factor(findings: &[DbFinding]) -> (f64, String) {
+    if findings.is_empty() {
+        return (0.0, "no detector findings".to_string());
+    }
+    // Every further hit halves the remaining distance to 1
+    let value = 1.0 - 0.5f64.powi(findings.len() as i32);
+    let mut detectors: Vec<String> =
+        findings.iter().map(|f| format!("{} ({} ICP)", f.detector, f.total_amount as f64 / ICP)).collect();
+    detectors.dedup();
+    (value, format!("{} finding(s): {}", findings.len(), detectors.join(", ")))
+}
+
+fn taint_factor(evidence: &AccountEvidence) -> (f64, String) {
+    let Some(taint) = evidence.taint else {
+        return (0.0, "no taint exposure recorded".to_string());
+    };
+    // The peak rather than the current holding, so accounts that passed tainted funds on still score
+    let peak = taint.history.iter().map(|p| p.tainted_e8s).chain([taint.tainted_e8s]).max().unwrap_or(0);
+    if peak == 0 {
+        return (0.0, "never held tainted funds".to_string());
+    }
+    let received: u64 = evidence
+        .transactions
+        .iter()
+        .filter(|tx| matches!(tx.operation_type.as_str(), "Transfer" | "Mint"))
+        .filter(|tx| tx.to_account.as_deref() == Some(evidence.account))
+        .filter(|tx| tx.from_account.as_deref() != Some(evidence.account))
+        .map(|tx| tx.amount.unwrap_or(0))
+        .sum();
+    let value = if received > 0 { (peak as f64 / received as f64).min(1.0) } else { 1.0 };
+    (
+        value,
+        format!(
+            "held up to {} ICP tainted, {:.1}% of everything it received; {} ICP tainted now",
+            peak as f64 / ICP,
+            value * 100.0,
+            taint.tainted_e8s as f64 / ICP
+        ),
+    )
+}
+
+fn counterparty_factor(evidence: &AccountEvidence, labels: &HashMap<String, (String, Type)>) -> (f64, String) {
+    if let Some((name, ty)) = labels.get(evidence.account).filter(|(_, ty)| RISKY_TYPES.contains(ty)) {
+        return (1.0, format!("account itself is labelled {} ({})", name, ty));
+    }
+
+    let mut total = 0u64;
+    let mut risky = 0u64;
+    let mut risky_names = HashSet::new();
+    for tx in evidence.transactions.iter().filter(|tx| tx.operation_type == "Transfer") {
+        let amount = tx.amount.unwrap_or(0);
+        let counterparty = if tx.from_account.as_deref() == Some(evidence.account) {
+            tx.to_account.as_deref()
+        } else {
+            tx.from_account.as_deref()
+        };
+        total += amount;
+        if let Some((name, _)) = counterparty.and_then(|c| labels.get(c)).filter(|(_, ty)| RISKY_TYPES.contains(ty)) {
+            risky += amount;
+            risky_names.insert(name.clone());
+        }
+    }
+
+    if risky == 0 {
+        return (0.0, "no transfers with suspect or spammer accounts".to_string());
+    }
+    let mut names: Vec<String> = risky_names.into_iter().collect();
+    names.sort();
+    let share = risky as f64 / total as f64;
+    (share, format!("{:.1}% of transferred volume with {}", share * 100.0, names.join(", ")))
+}
+
+fn age_factor(evidence: &AccountEvidence) -> (f64, String) {
+    let Some(first) = evidence.transactions.iter().filter_map(|tx| tx.timestamp).min() else {
+        return (0.0, "no transactions".to_string());
+    };
+    // Accounts younger than a year score linearly higher the newer they are
+    let age_days = evidence.reference_timestamp.saturating_sub(first) / NANOS_PER_DAY;
+    let value = (1.0 - age_days as f64 / 365.0).max(0.0);
+    (value, format!("first seen {} days before the reference time", age_days))
+}
+
+fn behaviour_factor(evidence: &AccountEvidence) -> (f64, String) {
+    let mut received = 0u64;
+    let mut sent = 0u64;
+    let mut sent_quickly = 0u64;
+    let mut last_receipt: Option<u64> = None;
+
+    let mut transactions: Vec<&DbTransaction> = evidence.transactions.iter().collect();
+    transactions.sort_by_key(|tx| tx.timestamp);
+    for tx in transactions {
+        let amount = tx.amount.unwrap_or(0);
+        let timestamp = tx.timestamp.unwrap_or(0);
+        let is_sender = tx.from_account.as_deref() == Some(evidence.account);
+        let is_recipient = tx.to_account.as_deref() == Some(evidence.account);
+        match tx.operation_type.as_str() {
+            "Transfer" | "Mint" if is_recipient && !is_sender => {
+                received += amount;
+                last_receipt = Some(timestamp);
+            }
+            "Transfer" | "Burn" if is_sender && !is_recipient => {
+                sent += amount;
+                if last_receipt.is_some_and(|r| timestamp - r <= NANOS_PER_DAY) {
+                    sent_quickly += amount;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if received == 0 || sent == 0 {
+        return (0.0, "funds only flow one way".to_string());
+    }
+    // Pass-through accounts forward what they receive, and do it quickly
+    let pass_through = sent.min(received) as f64 / received.max(sent) as f64;
+    let rapid = sent_quickly as f64 / sent as f64;
+    (
+        (pass_through + rapid) / 2.0,
+        format!(
+            "forwards {:.0}% of what it receives, {:.0}% of outflow within a day of a receipt",
+            pass_through * 100.0,
+            rapid * 100.0
+        ),
+    )
+}
+
+/// Score one account; factor contributions add up to the score
+pub fn score_account(
+    evidence: &AccountEvidence,
+    weights: &RiskWeights,
+    labels: &HashMap<String, (String, Type)>,
+) -> RiskScore {
+    let total_weight = weights.total();
+    let factors: Vec<RiskFactor> = [
+        ("patterns", weights.patterns, pattern_factor(evidence.findings)),
+        ("taint", weights.taint, taint_factor(evidence)),
+        ("counterparties", weights.counterparties, counterparty_factor(evidence, labels)),
+        ("age", weights.age, age_factor(evidence)),
+        ("behaviour", weights.behaviour, behaviour_factor(evidence)),
+    ]
+    .into_iter()
+    .map(|(name, weight, (value, explanation))| RiskFactor {
+        name,
+        value,
+        weight,
+        contribution: if total_weight > 0.0 { 100.0 * weight * value / total_weight } else { 0.0 },
+        explanation,
+    })
+    .collect();
+
+    RiskScore {
+        account: evidence.account.to_string(),
+        label: labels.get(evidence.account).map(|(name, ty)| format!("{} ({})", name, ty)),
+        score: factors.iter().map(|f| f.contribution).sum(),
+        factors,
+    }
+}
+
+/// Score `accounts`, or every account with a finding or taint exposure when None
+pub async fn run_risk_scores(
+    db_path: &str,
+    accounts: Option<Vec<String>>,
+    taint_path: &str,
+    weights: RiskWeights,
+) -> Result<()> {
+    println!("===== RISK SCORES =====");
+    println!("Database: {}", db_path);
+    println!("Weights: {:?}", weights);
+
+    let db = LedgerDatabase::new(db_path)?;
+    let labels = label_map();
+
+    let taint: HashMap<String, TaintExposure> = match std::fs::read_to_string(taint_path) {
+        Ok(contents) => {
+            let file: TaintFile = serde_json::from_str(&contents)?;
+            println!("Taint exposure: {} accounts from {}", file.accounts.len(), taint_path);
+            file.accounts.into_iter().map(|t| (t.account.clone(), t)).collect()
+        }
+        Err(_) => {
+            println!("Taint exposure: {} not found, run 'taint' first to include it", taint_path);
+            HashMap::new()
+        }
+    };
+
+    let mut findings: HashMap<String, Vec<DbFinding>> = HashMap::new();
+    // Findings a reviewer rejected no longer count against the account
+    for finding in
+        db.get_findings(&FindingFilter::default())?.into_iter().filter(|f| FindingStatus::is_active(&f.status))
+    {
+        findings.entry(finding.account.clone()).or_default().push(finding);
+    }
+
+    let accounts = accounts.unwrap_or_else(|| {
+        let mut all: Vec<String> = findings.keys().chain(taint.keys()).cloned().collect();
+        all.sort();
+        all.dedup();
+        all
+    });
+    println!("Scoring {} accounts", accounts.len());
+
+    let reference_timestamp = db.get_last_timestamp()?.unwrap_or(0);
+    let mut scores = Vec::new();
+    for account in &accounts {
+        let transactions = db.get_account_transactions(account)?;
+        let evidence = AccountEvidence {
+            account,
+            transactions: &transactions,
+            findings: findings.get(account).map(|f| f.as_slice()).unwrap_or(&[]),
+            taint: taint.get(account),
+            reference_timestamp,
+        };
+        scores.push(score_account(&evidence, &weights, &labels));
+    }
+    scores.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    println!("\nHighest risk:");
+    for score in scores.iter().take(20) {
+        println!(
+            "  {:5.1}  {} {}",
+            score.score,
+            &score.account[..8.min(score.account.len())],
+            score.label.as_deref().unwrap_or("")
+        );
+        for factor in score.factors.iter().filter(|f| f.contribution > 0.0) {
+            println!("         +{:.1} {}: {}", factor.contribution, factor.name, factor.explanation);
+        }
+    }
+
+    let report = RiskReport { weights, accounts: scores };
+    let file_name = "./risk_scores.json";
+    std::fs::write(file_name, serde_json::to_string_pretty(&report)?)?;
+
+    println!("\nRisk scores saved to: {}", file_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger_db::test_transaction;
+
+    #[test]
+    fn test_score_explains_each_factor() {
+        let transfer = |id: u64, from: &str, to: &str, amount: u64, day: u64| DbTransaction {
+            fee: Some(10_000),
+            timestamp: Some(day * NANOS_PER_DAY),
+            ..test_transaction(id, "Transfer", Some(from), Some(to), amount)
+        };
+        let transactions = [transfer(0, "source", "acct", 1000, 100), transfer(1, "acct", "next", 1000, 100)];
+        let finding = DbFinding {
+            id: 1,
+            finding_id: "f1".to_string(),
+            account: "acct".to_string(),
+            detector: "mixer".to_string(),
+            detector_version: "1".to_string(),
+            pattern_type: "MixerPattern".to_string(),
+            total_amount: 1000,
+            evidence_blocks: vec![0, 1],
+            pattern: "{}".to_string(),
+            status: "new".to_string(),
+            notes: None,
+            first_seen: String::new(),
+            last_seen: String::new(),
+            reviewed_at: None,
+        };
+        // A pass-through account: it held 500 tainted e8s and has forwarded everything since
+        let taint = TaintExposure {
+            account: "acct".to_string(),
+            tainted_e8s: 0,
+            taint_ratio: 0.0,
+            history: vec![TaintPoint { day: 100, tainted_e8s: 500, balance_e8s: 1000 }],
+        };
+        let evidence = AccountEvidence {
+            account: "acct",
+            transactions: &transactions,
+            findings: std::slice::from_ref(&finding),
+            taint: Some(&taint),
+            reference_timestamp: 100 * NANOS_PER_DAY,
+        };
+
+        let weights = RiskWeights::parse("patterns=1,taint=1,counterparties=0,age=1,behaviour=1").unwrap();
+        let score = score_account(&evidence, &weights, &HashMap::new());
+
+        // patterns 0.5, taint 0.5, age 1.0, behaviour 1.0, each a quarter of the score
+        let values: Vec<f64> = score.factors.iter().map(|f| f.value).collect();
+        assert_eq!(values, vec![0.5, 0.5, 0.0, 1.0, 1.0]);
+        assert!((score.score - 75.0).abs() < 1e-9);
+        assert!(score.factors.iter().all(|f| !f.explanation.is_empty()));
+        assert!(RiskWeights::parse("color=1").is_err());
+        assert!(RiskWeights::parse("taint=NaN").is_err());
+        assert!(RiskWeights::parse("taint=inf").is_err());
+    }
+}
@@ -0,0 +1,213 @@
+// Daily supply time series over ledger.db
+// Tracks total supply, mints, burns and burned fees, with mints broken down by destination
+
+use crate::{
+    entities::label_map,
+    ledger_db::{DbTransaction, LedgerDatabase, NANOS_PER_DAY},
+    replay::LedgerState,
+    Type,
+};
+use anyhow::Result;
+use chrono::DateTime;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+// Mints before the network's public launch (2021-05-10 UTC) are the genesis allocations
+const GENESIS_END_NANOS: u64 = 1_620_604_800 * 1_000_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MintCategory {
+    Genesis,
+    NodeProviderRewards,
+    // Every other mint comes from governance paying out neuron maturity (spawn / disburse_maturity)
+    NeuronMaturity,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SupplyDay {
+    pub day: u64,
+    pub date: String,
+    pub total_supply_e8s: i64,
+    pub minted_e8s: u64,
+    pub burned_e8s: u64,
+    pub fees_burned_e8s: u64,
+    pub mints_by_category: BTreeMap<MintCategory, u64>,
+}
+
+/// Classify a mint by its timestamp and destination
+pub fn classify_mint(timestamp: Option<u64>, to_is_node_provider: bool) -> MintCategory {
+    if timestamp.is_some_and(|ts| ts < GENESIS_END_NANOS) {
+        MintCategory::Genesis
+    } else if to_is_node_provider {
+        MintCategory::NodeProviderRewards
+    } else {
+        MintCategory::NeuronMaturity
+    }
+}
+
+/// Daily supply series built on top of a full ledger replay
+pub struct SupplySeries {
+    pub state: LedgerState,
+    labels: HashMap<String, (String, Type)>,
+    days: Vec<SupplyDay>,
+}
+
+impl SupplySeries {
+    pub fn new(labels: HashMap<String, (String, Type)>) -> Self {
+        Self { state: LedgerState::new(), labels, days: Vec::new() }
+    }
+
+    /// Apply a single transaction, opening a new day when its timestamp moves past the current one
+    pub fn apply(&mut self, tx: &DbTransaction) {
+        // Transactions without a timestamp, or stamped before the current day, are booked on the current day
+        let current_day = self.days.last().map(|d| d.day);
+        let day = match (tx.timestamp.map(|ts| ts / NANOS_PER_DAY), current_day) {
+            (Some(day), Some(current)) => day.max(current),
+            (day, current) => day.or(current).unwrap_or(0),
+        };
+        if current_day != Some(day) {
+            // Days without any activity carry the previous supply forward
+            let first_new_day = current_day.map_or(day, |current| current + 1);
+            let total_supply_e8s = self.state.total_supply();
+            self.days.extend((first_new_day..=day).map(|day| SupplyDay {
+                day,
+                total_supply_e8s,
+                ..Default::default()
+            }));
+        }
+
+        let (minted, burned, fees_burned) = (self.state.minted, self.state.burned, self.state.fees_burned);
+        self.state.apply(tx);
+
+        let current = self.days.last_mut().unwrap();
+        let minted = self.state.minted - minted;
+        current.minted_e8s += minted;
+        current.burned_e8s += self.state.burned - burned;
+        current.fees_burned_e8s += self.state.fees_burned - fees_burned;
+        current.total_supply_e8s = self.state.total_supply();
+
+        if tx.operation_type == "Mint" {
+            let to_is_node_provider = tx
+                .to_account
+                .as_ref()
+                .and_then(|to| self.labels.get(to))
+                .is_some_and(|(_, ty)| *ty == Type::NodeProvider);
+            let category = classify_mint(tx.timestamp, to_is_node_provider);
+            *current.mints_by_category.entry(category).or_insert(0) += minted;
+        }
+    }
+
+    /// Finish the series, filling in the calendar date of every day
+    pub fn finish(mut self) -> Vec<SupplyDay> {
+        for day in &mut self.days {
+            day.date = DateTime::from_timestamp((day.day * 24 * 60 * 60) as i64, 0)
+                .map(|dt| dt.format("%Y-%m-%d").to_string())
+                .unwrap_or_default();
+        }
+
+        self.days
+    }
+}
+
+/// Build the daily supply series by streaming the whole ledger
+pub fn build_supply_series(db: &LedgerDatabase) -> Result<Vec<SupplyDay>> {
+    let mut series = SupplySeries::new(label_map());
+
+    db.for_each_transaction(None, |tx| {
+        series.apply(&tx);
+        Ok(())
+    })?;
+
+    Ok(series.finish())
+}
+
+/// Run the supply series generation and save it for the graph frontend
+pub async fn run_supply_series(db_path: &str) -> Result<()> {
+    println!("===== SUPPLY SERIES =====");
+    println!("Database path: {}", db_path);
+
+    let db = LedgerDatabase::new(db_path)?;
+    let series = build_supply_series(&db)?;
+
+    if let Some(last) = series.last() {
+        let minted: u64 = series.iter().map(|d| d.minted_e8s).sum();
+        let burned: u64 = series.iter().map(|d| d.burned_e8s).sum();
+        let fees: u64 = series.iter().map(|d| d.fees_burned_e8s).sum();
+
+        println!("\nDays: {}", series.len());
+        println!("Total minted: {} ICP", minted as f64 / 100_000_000.0);
+        println!("Total burned: {} ICP", burned as f64 / 100_000_000.0);
+        println!("Fees burned: {} ICP", fees as f64 / 100_000_000.0);
+        println!("Supply on {}: {} ICP", last.date, last.total_supply_e8s as f64 / 100_000_000.0);
+    }
+
+    let output_path = "../graph/public/supply_series.json";
+    std::fs::write(output_path, serde_json::to_string_pretty(&series)?)?;
+
+    println!("\nSupply series saved to: {}", output_path);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger_db::test_transaction;
+
+    #[test]
+    fn test_classify_mint() {
+        assert_eq!(classify_mint(Some(GENESIS_END_NANOS - 1), true), MintCategory::Genesis);
+        assert_eq!(classify_mint(Some(GENESIS_END_NANOS), true), MintCategory::NodeProviderRewards);
+        assert_eq!(classify_mint(Some(GENESIS_END_NANOS), false), MintCategory::NeuronMaturity);
+        assert_eq!(classify_mint(None, false), MintCategory::NeuronMaturity);
+    }
+
+    fn tx(
+        id: u64,
+        op: &str,
+        from: Option<&str>,
+        to: Option<&str>,
+        amount: u64,
+        fee: u64,
+        timestamp: u64,
+    ) -> DbTransaction {
+        DbTransaction { fee: Some(fee), timestamp: Some(timestamp), ..test_transaction(id, op, from, to, amount) }
+    }
+
+    #[test]
+    fn test_supply_series() {
+        let labels = HashMap::from([("np".to_string(), ("Provider".to_string(), Type::NodeProvider))]);
+        let mut series = SupplySeries::new(labels);
+
+        // The launch day starts right at GENESIS_END_NANOS
+        let launch = GENESIS_END_NANOS;
+        series.apply(&tx(0, "Mint", None, Some("gen"), 1_000, 0, launch - 1));
+        series.apply(&tx(1, "Mint", None, Some("np"), 500, 0, launch));
+        series.apply(&tx(2, "Mint", None, Some("neuron"), 200, 0, launch + 1));
+        series.apply(&tx(3, "Transfer", Some("gen"), Some("x"), 100, 10, launch + 2 * NANOS_PER_DAY));
+        series.apply(&tx(4, "Burn", Some("x"), None, 50, 0, launch + 2 * NANOS_PER_DAY + 1));
+        let days = series.finish();
+
+        let launch_day = launch / NANOS_PER_DAY;
+        assert_eq!(
+            days.iter().map(|d| d.day).collect::<Vec<_>>(),
+            (launch_day - 1..=launch_day + 2).collect::<Vec<_>>()
+        );
+        assert_eq!(days.iter().map(|d| d.total_supply_e8s).collect::<Vec<_>>(), vec![1_000, 1_700, 1_700, 1_640]);
+        assert_eq!(days[1].date, "2021-05-10");
+
+        assert_eq!(days[0].mints_by_category, BTreeMap::from([(MintCategory::Genesis, 1_000)]));
+        assert_eq!(
+            days[1].mints_by_category,
+            BTreeMap::from([(MintCategory::NodeProviderRewards, 500), (MintCategory::NeuronMaturity, 200)])
+        );
+        assert_eq!(days[1].minted_e8s, 700);
+
+        // The gap day carries the supply with no flows
+        assert_eq!((days[2].minted_e8s, days[2].burned_e8s, days[2].fees_burned_e8s), (0, 0, 0));
+        assert!(days[2].mints_by_category.is_empty());
+
+        assert_eq!((days[3].burned_e8s, days[3].fees_burned_e8s), (50, 10));
+    }
+}
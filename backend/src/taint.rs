@@ -0,0 +1,611 @@
+// Taint propagation over a ledger replay
+// Follows traced funds through every later transaction under a chosen policy
+// (FIFO, LIFO, pro-rata haircut or poison) and reports where they sit now
+
+use crate::{
+    entities::label_map,
+    ledger_db::{DbTransaction, LedgerDatabase, NANOS_PER_DAY},
+    replay::LedgerState,
+    Type,
+};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+/// How traced funds mix with the rest of an account's balance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Policy {
+    /// Oldest funds leave first
+    Fifo,
+    /// Newest funds leave first
+    Lifo,
+    /// Every outflow carries the account's current traced share (haircut)
+    ProRata,
+    /// Any traced funds make every later outflow fully traced
+    Poison,
+}
+
+impl Policy {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "fifo" => Ok(Self::Fifo),
+            "lifo" => Ok(Self::Lifo),
+            "pro-rata" | "pro_rata" | "prorata" | "haircut" => Ok(Self::ProRata),
+            "poison" => Ok(Self::Poison),
+            other => bail!("unknown policy '{}', expected fifo, lifo, pro-rata or poison", other),
+        }
+    }
+}
+
+/// Where propagation stops; traced funds reaching these accounts stay there
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopAt {
+    Exchanges,
+    Labelled,
+    Nothing,
+}
+
+impl StopAt {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "cex" | "exchanges" => Ok(Self::Exchanges),
+            "labelled" | "labeled" => Ok(Self::Labelled),
+            "none" => Ok(Self::Nothing),
+            other => bail!("unknown stop condition '{}', expected exchanges, labelled or none", other),
+        }
+    }
+
+    /// The accounts this condition stops at
+    pub fn accounts(&self) -> HashSet<String> {
+        label_map()
+            .into_iter()
+            .filter(|(_, (_, ty))| match self {
+                Self::Exchanges => *ty == Type::Cex,
+                Self::Labelled => true,
+                Self::Nothing => false,
+            })
+            .map(|(account, _)| account)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Lot {
+    amount: u64,
+    traced: bool,
+}
+
+/// Take `amount` out of a lot queue, returning how much of it was traced
+fn consume(lots: &mut VecDeque<Lot>, mut amount: u64, newest_first: bool) -> u64 {
+    let mut traced = 0;
+
+    while amount > 0 {
+        let lot = if newest_first { lots.back_mut() } else { lots.front_mut() };
+        let Some(lot) = lot else { break };
+
+        let taken = lot.amount.min(amount);
+        lot.amount -= taken;
+        amount -= taken;
+        if lot.traced {
+            traced += taken;
+        }
+
+        if lot.amount == 0 {
+            if newest_first {
+                lots.pop_back();
+            } else {
+                lots.pop_front();
+            }
+        }
+    }
+
+    traced
+}
+
+/// Replays transactions while moving traced amounts between accounts
+pub struct TaintTracker {
+    pub policy: Policy,
+    pub state: LedgerState,
+    /// Traced amount currently held per account
+    pub traced: HashMap<String, u64>,
+    /// Traced amount destroyed by burns and fees
+    pub traced_burned: u64,
+    /// Accounts whose outflows are always fully traced
+    pub sources: HashSet<String>,
+    /// Accounts that absorb traced funds without passing them on
+    pub stops: HashSet<String>,
+    lots: HashMap<String, VecDeque<Lot>>,
+    poisoned: HashSet<String>,
+}
+
+impl TaintTracker {
+    pub fn new(policy: Policy, state: LedgerState, sources: HashSet<String>, stops: HashSet<String>) -> Self {
+        Self {
+            policy,
+            state,
+            traced: HashMap::new(),
+            traced_burned: 0,
+            sources,
+            stops,
+            lots: HashMap::new(),
+            poisoned: HashSet::new(),
+        }
+    }
+
+    fn balance(&self, account: &str) -> u64 {
+        self.state.balances.get(account).copied().unwrap_or(0).max(0) as u64
+    }
+
+    /// Apply a transaction, returning the traced part of its amount
+    pub fn apply(&mut self, tx: &DbTransaction) -> u64 {
+        let amount = tx.amount.unwrap_or(0);
+        let fee = tx.fee.unwrap_or(0);
+
+        let traced = match (tx.operation_type.as_str(), &tx.from_account, &tx.to_account) {
+            ("Transfer", Some(from), Some(to)) => {
+                let traced = self.debit(from, amount, fee);
+                self.credit(to, amount, traced);
+                traced
+            }
+            ("Mint", _, Some(to)) => {
+                self.credit(to, amount, 0);
+                0
+            }
+            ("Burn", Some(from), _) => {
+                let traced = self.debit(from, amount, 0);
+                self.traced_burned += traced;
+                traced
+            }
+            ("Approve", Some(from), _) => {
+                self.debit(from, 0, fee);
+                0
+            }
+            _ => 0,
+        };
+
+        self.state.apply(tx);
+        traced
+    }
+
+    /// Apply a transaction whose whole amount becomes traced at the recipient
+    pub fn apply_traced(&mut self, tx: &DbTransaction) -> u64 {
+        let amount = tx.amount.unwrap_or(0);
+
+        if let Some(from) = tx.from_account.as_ref().filter(|_| tx.operation_type == "Transfer") {
+            self.debit(from, amount, tx.fee.unwrap_or(0));
+        }
+        if let Some(to) = &tx.to_account {
+            self.credit(to, amount, amount);
+        }
+
+        self.state.apply(tx);
+        amount
+    }
+
+    /// Remove `amount + fee` from an account, returning the traced part of `amount`
+    fn debit(&mut self, account: &str, amount: u64, fee: u64) -> u64 {
+        if self.sources.contains(account) {
+            return amount;
+        }
+        if self.stops.contains(account) {
+            return 0;
+        }
+
+        let held = self.traced.get(account).copied().unwrap_or(0);
+        if held == 0 && !self.poisoned.contains(account) {
+            return 0;
+        }
+
+        let (traced_amount, traced_fee) = match self.policy {
+            Policy::Poison => {
+                let traced_fee = fee.min(held);
+                (amount, traced_fee)
+            }
+            Policy::ProRata => {
+                let balance = self.balance(account).max(held).max(1);
+                let share = |value: u64| (value as u128 * held as u128 / balance as u128) as u64;
+                (share(amount), share(fee))
+            }
+            Policy::Fifo | Policy::Lifo => {
+                let newest_first = self.policy == Policy::Lifo;
+                let lots = self.lots.entry(account.to_string()).or_default();
+                let traced_amount = consume(lots, amount, newest_first);
+                (traced_amount, consume(lots, fee, newest_first))
+            }
+        };
+
+        let remaining = held.saturating_sub(traced_amount + traced_fee);
+        if remaining == 0 && self.policy != Policy::Poison {
+            self.traced.remove(account);
+            self.lots.remove(account);
+        } else {
+            self.traced.insert(account.to_string(), remaining);
+        }
+        self.traced_burned += traced_fee;
+
+        traced_amount
+    }
+
+    /// Add `amount` to an account, `traced` of which is traced
+    fn credit(&mut self, account: &str, amount: u64, traced: u64) {
+        if self.sources.contains(account) {
+            return;
+        }
+        let tracked = self.traced.contains_key(account) || self.poisoned.contains(account);
+        if traced == 0 && !tracked {
+            return;
+        }
+
+        match self.policy {
+            Policy::Poison => {
+                // A poisoned account's whole balance counts as traced
+                self.poisoned.insert(account.to_string());
+                let held = if tracked { self.traced.get(account).copied().unwrap_or(0) } else { self.balance(account) };
+                self.traced.insert(account.to_string(), held + amount);
+            }
+            Policy::ProRata => {
+                *self.traced.entry(account.to_string()).or_insert(0) += traced;
+            }
+            Policy::Fifo | Policy::Lifo => {
+                let balance = self.balance(account);
+                let lots = self.lots.entry(account.to_string()).or_insert_with(|| {
+                    // Funds already held before the first traced arrival
+                    let mut lots = VecDeque::new();
+                    if balance > 0 {
+                        lots.push_back(Lot { amount: balance, traced: false });
+                    }
+                    lots
+                });
+                if amount > traced {
+                    lots.push_back(Lot { amount: amount - traced, traced: false });
+                }
+                if traced > 0 {
+                    lots.push_back(Lot { amount: traced, traced: true });
+                }
+                *self.traced.entry(account.to_string()).or_insert(0) += traced;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TracedHolding {
+    pub account: String,
+    pub label: Option<String>,
+    pub balance_e8s: i64,
+    pub traced_e8s: u64,
+    pub share_of_traced: f64,
+    pub stopped: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ForwardTraceReport {
+    pub block: u64,
+    pub policy: Policy,
+    pub stop_at: StopAt,
+    pub traced_e8s: u64,
+    pub last_block: Option<u64>,
+    pub burned_e8s: u64,
+    pub absorbed_by_entity: BTreeMap<String, u64>,
+    pub holdings: Vec<TracedHolding>,
+}
+
+/// Where the traced funds sit once the tracker has processed the ledger
+pub fn traced_holdings(tracker: &TaintTracker, total: u64) -> Vec<TracedHolding> {
+    let labels = label_map();
+
+    let mut holdings: Vec<TracedHolding> = tracker
+        .traced
+        .iter()
+        .filter(|(_, traced)| **traced > 0)
+        .map(|(account, traced)| TracedHolding {
+            account: account.clone(),
+            label: labels.get(account).map(|(name, ty)| format!("{} ({})", name, ty)),
+            balance_e8s: tracker.state.balances.get(account).copied().unwrap_or(0),
+            traced_e8s: *traced,
+            share_of_traced: *traced as f64 / total.max(1) as f64,
+            stopped: tracker.stops.contains(account),
+        })
+        .collect();
+    holdings.sort_by(|a, b| b.traced_e8s.cmp(&a.traced_e8s).then_with(|| a.account.cmp(&b.account)));
+
+    holdings
+}
+
+/// Trace the funds moved in `block` forward to the end of the ledger
+pub fn trace_forward(db: &LedgerDatabase, block: u64, policy: Policy, stop_at: StopAt) -> Result<ForwardTraceReport> {
+    let mut tracker = TaintTracker::new(policy, LedgerState::new(), HashSet::new(), stop_at.accounts());
+    let mut traced_total = None;
+
+    db.for_each_transaction(None, |tx| {
+        if tx.id < block {
+            tracker.state.apply(&tx);
+        } else if tx.id == block {
+            traced_total = Some(tracker.apply_traced(&tx));
+        } else {
+            tracker.apply(&tx);
+        }
+        Ok(())
+    })?;
+
+    let Some(traced_total) = traced_total else { bail!("block {} not found in the database", block) };
+
+    let labels = label_map();
+    let mut absorbed_by_entity = BTreeMap::new();
+    for account in tracker.stops.iter().filter(|a| tracker.traced.contains_key(*a)) {
+        if let Some((name, _)) = labels.get(account) {
+            *absorbed_by_entity.entry(name.clone()).or_insert(0) += tracker.traced[account];
+        }
+    }
+
+    Ok(ForwardTraceReport {
+        block,
+        policy,
+        stop_at,
+        traced_e8s: traced_total,
+        last_block: tracker.state.last_block,
+        burned_e8s: tracker.traced_burned,
+        absorbed_by_entity,
+        holdings: traced_holdings(&tracker, traced_total),
+    })
+}
+
+/// Run a forward trace against ledger.db
+pub async fn run_trace_forward(db_path: &str, block: u64, policy: Policy, stop_at: StopAt) -> Result<()> {
+    println!("===== FORWARD TRACE =====");
+    println!("Block: {}", block);
+    println!("Policy: {:?}, stop at: {:?}", policy, stop_at);
+
+    let db = LedgerDatabase::new(db_path)?;
+    let report = trace_forward(&db, block, policy, stop_at)?;
+
+    println!("\nTraced amount: {} ICP", report.traced_e8s as f64 / 100_000_000.0);
+    println!("Burned: {} ICP", report.burned_e8s as f64 / 100_000_000.0);
+    println!("Accounts holding traced funds: {}", report.holdings.len());
+
+    println!("\nAbsorbed by labelled entities:");
+    for (entity, amount) in &report.absorbed_by_entity {
+        println!("  {}: {} ICP", entity, *amount as f64 / 100_000_000.0);
+    }
+
+    println!("\nLargest holdings:");
+    for holding in report.holdings.iter().take(20) {
+        println!(
+            "  {} {} - {} ICP ({:.2}%)",
+            &holding.account[..8],
+            holding.label.as_deref().unwrap_or(""),
+            holding.traced_e8s as f64 / 100_000_000.0,
+            holding.share_of_traced * 100.0
+        );
+    }
+
+    let file_name = format!("./trace_forward_{}.json", block);
+    std::fs::write(&file_name, serde_json::to_string_pretty(&report)?)?;
+
+    println!("\nForward trace saved to: {}", file_name);
+
+    Ok(())
+}
+
+/// Tainted amount and balance of an account at the end of a day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaintPoint {
+    pub day: u64,
+    pub tainted_e8s: u64,
+    pub balance_e8s: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaintedAccount {
+    pub account: String,
+    pub label: Option<String>,
+    pub balance_e8s: i64,
+    pub tainted_e8s: u64,
+    /// Tainted share of the current balance
+    pub taint_ratio: f64,
+    /// Block at which the account first held the tracking threshold
+    pub first_tainted_block: u64,
+    pub history: Vec<TaintPoint>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaintReport {
+    pub seeds: usize,
+    pub policy: Policy,
+    pub stop_at: StopAt,
+    pub last_block: Option<u64>,
+    pub tainted_in_circulation_e8s: u64,
+    pub tainted_burned_e8s: u64,
+    pub accounts: Vec<TaintedAccount>,
+}
+
+/// Daily taint history of the accounts holding at least `min_tainted` traced e8s at some point
+struct TaintHistory {
+    min_tainted: u64,
+    points: HashMap<String, Vec<TaintPoint>>,
+    first_tainted: HashMap<String, u64>,
+}
+
+impl TaintHistory {
+    fn new(min_tainted: u64) -> Self {
+        Self { min_tainted: min_tainted.max(1), points: HashMap::new(), first_tainted: HashMap::new() }
+    }
+
+    /// Record the accounts touched by `tx`, after the tracker has applied it
+    fn record(&mut self, tracker: &TaintTracker, tx: &DbTransaction) {
+        let day = tx.timestamp.unwrap_or(0) / NANOS_PER_DAY;
+        for account in [&tx.from_account, &tx.to_account].into_iter().flatten() {
+            let tainted = tracker.traced.get(account).copied().unwrap_or(0);
+            // Dust-level taint reaches a large part of the ledger; only track accounts past the threshold
+            if tainted < self.min_tainted && !self.points.contains_key(account) {
+                continue;
+            }
+            self.first_tainted.entry(account.clone()).or_insert(tx.id);
+
+            let point = TaintPoint {
+                day,
+                tainted_e8s: tainted,
+                balance_e8s: tracker.state.balances.get(account).copied().unwrap_or(0),
+            };
+            let points = self.points.entry(account.clone()).or_default();
+            match points.last_mut() {
+                Some(last) if last.day == day => *last = point,
+                _ => points.push(point),
+            }
+        }
+    }
+}
+
+/// Propagate taint from a seed set over the whole ledger, keeping a daily history for every
+/// account that held at least `min_tainted` traced e8s
+pub fn taint_analysis(
+    db: &LedgerDatabase,
+    seeds: HashSet<String>,
+    policy: Policy,
+    stop_at: StopAt,
+    min_tainted: u64,
+) -> Result<TaintReport> {
+    let seed_count = seeds.len();
+    let mut tracker = TaintTracker::new(policy, LedgerState::new(), seeds, stop_at.accounts());
+    let mut history = TaintHistory::new(min_tainted);
+
+    db.for_each_transaction(None, |tx| {
+        tracker.apply(&tx);
+        history.record(&tracker, &tx);
+        Ok(())
+    })?;
+
+    let labels = label_map();
+    let first_tainted = history.first_tainted;
+    let mut accounts: Vec<TaintedAccount> = history
+        .points
+        .into_iter()
+        .map(|(account, history)| {
+            let balance = tracker.state.balances.get(&account).copied().unwrap_or(0);
+            let tainted = tracker.traced.get(&account).copied().unwrap_or(0);
+            TaintedAccount {
+                label: labels.get(&account).map(|(name, ty)| format!("{} ({})", name, ty)),
+                balance_e8s: balance,
+                tainted_e8s: tainted,
+                taint_ratio: if balance > 0 { (tainted as f64 / balance as f64).min(1.0) } else { 0.0 },
+                first_tainted_block: first_tainted[&account],
+                history,
+                account,
+            }
+        })
+        .collect();
+    accounts.sort_by(|a, b| b.tainted_e8s.cmp(&a.tainted_e8s).then_with(|| a.account.cmp(&b.account)));
+
+    Ok(TaintReport {
+        seeds: seed_count,
+        policy,
+        stop_at,
+        last_block: tracker.state.last_block,
+        tainted_in_circulation_e8s: tracker.traced.values().sum(),
+        tainted_burned_e8s: tracker.traced_burned,
+        accounts,
+    })
+}
+
+/// Run the taint analysis against ledger.db
+pub async fn run_taint(
+    db_path: &str,
+    seeds: Vec<String>,
+    policy: Policy,
+    stop_at: StopAt,
+    min_tainted: u64,
+) -> Result<()> {
+    println!("===== TAINT ANALYSIS =====");
+    println!("Seeds: {}", seeds.len());
+    println!("Policy: {:?}, stop at: {:?}", policy, stop_at);
+    println!("Tracking accounts with at least {} ICP tainted", min_tainted as f64 / 100_000_000.0);
+
+    let db = LedgerDatabase::new(db_path)?;
+    let report = taint_analysis(&db, seeds.into_iter().collect(), policy, stop_at, min_tainted)?;
+
+    println!("\nTainted in circulation: {} ICP", report.tainted_in_circulation_e8s as f64 / 100_000_000.0);
+    println!("Tainted burned: {} ICP", report.tainted_burned_e8s as f64 / 100_000_000.0);
+    println!("Accounts tracked: {}", report.accounts.len());
+
+    println!("\nMost tainted accounts:");
+    for account in report.accounts.iter().take(20) {
+        println!(
+            "  {} {} - {} ICP ({:.1}% of balance)",
+            &account.account[..8],
+            account.label.as_deref().unwrap_or(""),
+            account.tainted_e8s as f64 / 100_000_000.0,
+            account.taint_ratio * 100.0
+        );
+    }
+
+    let file_name = "./taint_report.json";
+    std::fs::write(file_name, serde_json::to_string_pretty(&report)?)?;
+
+    println!("\nTaint report saved to: {}", file_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger_db::test_transaction;
+
+    fn transfer(id: u64, from: &str, to: &str, amount: u64) -> DbTransaction {
+        test_transaction(id, "Transfer", Some(from), Some(to), amount)
+    }
+
+    fn mint(id: u64, to: &str, amount: u64) -> DbTransaction {
+        test_transaction(id, "Mint", None, Some(to), amount)
+    }
+
+    /// "a" holds 100 clean, receives 50 traced, then sends 60 to "b"
+    fn run(policy: Policy) -> TaintTracker {
+        let mut tracker = TaintTracker::new(policy, LedgerState::new(), HashSet::new(), HashSet::new());
+        tracker.apply(&mint(1, "a", 100));
+        tracker.apply(&mint(2, "s", 50));
+        tracker.apply_traced(&transfer(3, "s", "a", 50));
+        tracker.apply(&transfer(4, "a", "b", 60));
+        tracker
+    }
+
+    #[test]
+    fn test_policies() {
+        let fifo = run(Policy::Fifo);
+        assert_eq!(fifo.traced.get("b"), None);
+        assert_eq!(fifo.traced["a"], 50);
+
+        let lifo = run(Policy::Lifo);
+        assert_eq!(lifo.traced["b"], 50);
+        assert_eq!(lifo.traced.get("a"), None);
+
+        let pro_rata = run(Policy::ProRata);
+        assert_eq!(pro_rata.traced["b"], 20);
+        assert_eq!(pro_rata.traced["a"], 30);
+
+        let poison = run(Policy::Poison);
+        assert_eq!(poison.traced["b"], 60);
+        assert_eq!(poison.traced["a"], 90);
+    }
+
+    #[test]
+    fn test_dust_taint_is_not_tracked() {
+        let mut tracker = TaintTracker::new(Policy::ProRata, LedgerState::new(), HashSet::new(), HashSet::new());
+        let mut history = TaintHistory::new(25);
+        tracker.apply(&mint(1, "a", 100));
+        tracker.apply(&mint(2, "s", 50));
+        tracker.apply_traced(&transfer(3, "s", "a", 50));
+        history.record(&tracker, &transfer(3, "s", "a", 50));
+
+        // "b" receives 20 traced, below the threshold; "a" keeps being tracked as it drops to 30
+        let outflow = transfer(4, "a", "b", 60);
+        tracker.apply(&outflow);
+        history.record(&tracker, &outflow);
+
+        assert_eq!(history.points.keys().collect::<Vec<_>>(), vec!["a"]);
+        assert_eq!(history.first_tainted["a"], 3);
+        assert_eq!(history.points["a"].last().unwrap().tainted_e8s, 30);
+    }
+}
@@ -198,6 +198,8 @@ pub struct SimplifiedTransfer {
     pub id: u64,
     pub timestamp: u64,
     pub amount: u64,
+    #[serde(default)]
+    pub fee: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -375,7 +377,7 @@ pub async fn fetch_transactions(
     let simplified_transactions: Vec<SimplifiedTransfer> = all_transactions
         .into_iter()
         .filter_map(|tx_with_id| {
-            if let Operation::Transfer { to, from, amount, .. } = &tx_with_id.transaction.operation {
+            if let Operation::Transfer { to, from, amount, fee, .. } = &tx_with_id.transaction.operation {
                 Some(SimplifiedTransfer {
                     op_type: get_operation_type(&tx_with_id.transaction.operation).to_string(),
                     from: from.clone(),
@@ -383,6 +385,7 @@ pub async fn fetch_transactions(
                     id: tx_with_id.id,
                     timestamp: tx_with_id.transaction.timestamp.map(|ts| ts.timestamp_nanos).unwrap_or(0),
                     amount: amount.e8s,
+                    fee: fee.e8s,
                 })
             } else {
                 None
@@ -0,0 +1,123 @@
+// In-memory transfer graph loaded from ledger.db
+// Every transfer is kept as its own timestamped edge so traversals can respect time ordering
+
+use crate::ledger_db::{DbTransaction, LedgerDatabase, NANOS_PER_DAY};
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// A single transfer between two accounts
+#[derive(Debug, Clone, Serialize)]
+pub struct TransferEdge {
+    pub block: u64,
+    pub from: String,
+    pub to: String,
+    pub amount: u64,
+    pub timestamp: u64,
+}
+
+/// Inclusive time range in nanoseconds
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimeWindow {
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+}
+
+impl TimeWindow {
+    /// Parse "YYYY-MM-DD" bounds; the end date includes the whole day
+    pub fn parse(start: Option<&str>, end: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            start: start.map(date_to_nanos).transpose()?,
+            end: end.map(|d| date_to_nanos(d).map(|ts| ts + NANOS_PER_DAY - 1)).transpose()?,
+        })
+    }
+
+    pub fn contains(&self, timestamp: u64) -> bool {
+        self.start.is_none_or(|start| timestamp >= start) && self.end.is_none_or(|end| timestamp <= end)
+    }
+}
+
+fn date_to_nanos(value: &str) -> Result<u64> {
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| anyhow!("invalid date '{}': {}", value, e))?;
+    Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_nanos_opt().unwrap_or(0) as u64)
+}
+
+/// Which transfers make it into the graph
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GraphFilter {
+    pub min_amount: u64,
+    pub window: TimeWindow,
+}
+
+impl GraphFilter {
+    fn accepts(&self, tx: &DbTransaction) -> bool {
+        tx.operation_type == "Transfer"
+            && tx.amount.unwrap_or(0) >= self.min_amount
+            && tx.timestamp.is_some_and(|ts| self.window.contains(ts))
+    }
+}
+
+/// Transfer edges indexed by sender and recipient, each list in time order
+#[derive(Debug, Default)]
+pub struct TransferGraph {
+    pub edges: Vec<TransferEdge>,
+    outgoing: HashMap<String, Vec<usize>>,
+    incoming: HashMap<String, Vec<usize>>,
+}
+
+impl TransferGraph {
+    /// Load every transfer matching `filter`
+    pub fn load(db: &LedgerDatabase, filter: &GraphFilter) -> Result<Self> {
+        let mut edges = Vec::new();
+        db.for_each_transaction(None, |tx| {
+            if filter.accepts(&tx) {
+                if let (Some(from), Some(to)) = (tx.from_account, tx.to_account) {
+                    edges.push(TransferEdge {
+                        block: tx.id,
+                        from,
+                        to,
+                        amount: tx.amount.unwrap_or(0),
+                        timestamp: tx.timestamp.unwrap_or(0),
+                    });
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(Self::from_edges(edges))
+    }
+
+    pub fn from_edges(mut edges: Vec<TransferEdge>) -> Self {
+        edges.sort_by_key(|e| (e.timestamp, e.block));
+
+        let mut outgoing: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut incoming: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, edge) in edges.iter().enumerate() {
+            outgoing.entry(edge.from.clone()).or_default().push(i);
+            incoming.entry(edge.to.clone()).or_default().push(i);
+        }
+
+        Self { edges, outgoing, incoming }
+    }
+
+    /// Transfers sent by `account`, oldest first
+    pub fn outgoing(&self, account: &str) -> impl Iterator<Item = &TransferEdge> {
+        self.outgoing.get(account).into_iter().flatten().map(|i| &self.edges[*i])
+    }
+
+    /// Indices into `edges` of the transfers sent by `account`, oldest first
+    pub fn outgoing_indices(&self, account: &str) -> &[usize] {
+        self.outgoing.get(account).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    /// Transfers received by `account`, oldest first
+    pub fn incoming(&self, account: &str) -> impl Iterator<Item = &TransferEdge> {
+        self.incoming.get(account).into_iter().flatten().map(|i| &self.edges[*i])
+    }
+
+    /// Every account that sent or received a transfer
+    pub fn accounts(&self) -> impl Iterator<Item = &String> {
+        self.outgoing.keys().chain(self.incoming.keys().filter(|a| !self.outgoing.contains_key(*a)))
+    }
+}
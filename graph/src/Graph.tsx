@@ -27,6 +27,12 @@ interface GraphProps {
   onNodeClick?: (node: GraphNode) => void;
   onLinkClick?: (data: any) => void;
   highlightNodeId?: string; // Node to highlight (by id)
+  colorBy?: "group" | "community";
+}
+
+// Spread community ids around the hue wheel (golden angle) so neighbours get distinct colours.
+function communityColor(community: number): string {
+  return `hsl(${(community * 137.508) % 360}, 65%, 50%)`;
 }
 
 const Graph: React.FC<GraphProps> = ({
@@ -36,6 +42,7 @@ const Graph: React.FC<GraphProps> = ({
   onNodeClick,
   onLinkClick,
   highlightNodeId,
+  colorBy = "group",
 }) => {
   const svgRef = useRef<SVGSVGElement>(null);
   const zoomRef = useRef<any>(null);
@@ -206,6 +213,9 @@ const Graph: React.FC<GraphProps> = ({
       .attr("id", (d: any) => d.id ?? "")
       .attr("r", 10)
       .attr("fill", (d) => {
+        if (colorBy === "community" && d.community !== undefined) {
+          return communityColor(d.community);
+        }
         switch (d.group) {
           case "Cex":
             return "blue";
@@ -350,7 +360,7 @@ const Graph: React.FC<GraphProps> = ({
       simulation.stop();
     };
     // eslint-disable-next-line react-hooks/exhaustive-deps
-  }, [data, width, height]);
+  }, [data, width, height, colorBy]);
 
   useEffect(() => {
     if (!svgRef.current) return;
@@ -25,6 +25,8 @@ export function GraphContainer({
 }: GraphContainerProps) {
   const [highlightNodeId, setHighlightNodeId] = useState<string | undefined>(undefined);
   const [selectData, setSelectData] = useState<any[]>([]);
+  const [colorByCommunity, setColorByCommunity] = useState<boolean>(false);
+  const hasCommunities = data.nodes.some((node) => node.community !== undefined);
 
   useEffect(() => {
     const selectValues = [...data.nodes].map((node) => {
@@ -57,6 +59,20 @@ export function GraphContainer({
   return (
     <div className="graph-container">
       <Select options={selectData} placeholder="Find node..." onChange={handleSearch} />
+      {hasCommunities && (
+        <div className="form-check form-switch text-start mt-2">
+          <input
+            className="form-check-input"
+            type="checkbox"
+            id="colorByCommunity"
+            checked={colorByCommunity}
+            onChange={(e) => setColorByCommunity(e.target.checked)}
+          />
+          <label className="form-check-label" htmlFor="colorByCommunity">
+            Colour by community
+          </label>
+        </div>
+      )}
       <br />
       <Graph
         data={data}
@@ -65,6 +81,7 @@ export function GraphContainer({
         onNodeClick={onNodeClick}
         onLinkClick={onLinkClick}
         highlightNodeId={highlightNodeId}
+        colorBy={colorByCommunity ? "community" : "group"}
       />
     </div>
   );
@@ -3,7 +3,7 @@ import { AccountData, GraphData, GraphNode } from "./types";
 import { useWindowSize } from "./hooks/useWindowSize";
 import { GraphContainer } from "./GraphContainer";
 import { ToastMessage, useToast } from "./utils/Toast";
-import { buildGraph } from "./graphData";
+import { applyCommunities, buildGraph } from "./graphData";
 
 
 export default function Home() {
@@ -28,7 +28,14 @@ export default function Home() {
         );
         const results = await Promise.all(fetchPromises);
         const mergedData: AccountData[] = results.flat();
-        const graphData = buildGraph(mergedData);
+        let graphData = buildGraph(mergedData);
+        // communities.json is optional, generated by the backend `communities` mode
+        const communities = await fetch("/communities.json")
+          .then((res) => (res.ok ? res.json() : null))
+          .catch(() => null);
+        if (communities) {
+          graphData = applyCommunities(graphData, communities);
+        }
         setData(graphData);
         // await new Promise(resolve => setTimeout(resolve, 5000));
       } catch (err) {
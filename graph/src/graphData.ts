@@ -1,6 +1,6 @@
 /* eslint-disable @typescript-eslint/no-explicit-any */
 /* eslint-disable @typescript-eslint/no-unused-vars */
-import { AccountData, GraphNode, GraphLink, Direction } from "./types";
+import { AccountData, GraphNode, GraphLink, Direction, GraphData, CommunityData } from "./types";
 // Defi -- (NODE) ghost nodes (hidden but in the data) 
 // Cex -- (LINK) cex to cex excluded but others should show up
 // Foundation -- (LINK) foundation to foundation excluded but others should show up
@@ -265,3 +265,14 @@ connectionMap.forEach((externalIds, key) => {
 function initials(name: string): string {
   return name.replace(/\s+/g, "").slice(0, 2);
 }
+
+// Set each node's community from the backend community detection output.
+// A node takes the community of its main account, or of its first extra account that has one.
+export function applyCommunities(graph: GraphData, communities: CommunityData): GraphData {
+  const nodes = graph.nodes.map(node => {
+    const accounts: string[] = [node.id, ...(node.extra_info?.extra_accounts ?? []).map((a: [string, number]) => a[0])];
+    const account = accounts.find(a => communities.assignments[a] !== undefined);
+    return account !== undefined ? { ...node, community: communities.assignments[account] } : node;
+  });
+  return { ...graph, nodes };
+}
@@ -32,6 +32,7 @@ export interface AccountData {
     label: string; // e.g. the name
     group: string; // e.g. "Exchange", "Individual"
     color?: string;
+    community?: number; // community id from the backend's community detection
     mainAccounts?: string[];
     defiTxs?: Transaction[];
     extra_info?: any;
@@ -67,3 +68,21 @@ export interface GraphData {
   nodes: GraphNode[];
   links: GraphLink[]; 
 };
+
+// Output of the backend `communities` mode (communities.json)
+export interface CommunitySummary {
+  id: number;
+  size: number;
+  accounts: string[];
+  labels: string[];
+  internal_volume_e8s: number;
+  inflow_e8s: number;
+  outflow_e8s: number;
+  total_balance_e8s?: number;
+}
+
+export interface CommunityData {
+  modularity: number;
+  communities: CommunitySummary[];
+  assignments: { [account: string]: number };
+}